@@ -3,130 +3,422 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+// shared with tests/sha256.rs
+#[path = "build/sha256.rs"]
+mod sha256;
+
+use sha256::{sha256, to_hex};
+
+// the architecture this build expects, keep in sync with VALUE_ARCH and POLICY_ARCH in src/nets
+// net files can either be raw weights (what the trainers spit out), which get this header
+// put on them, or already have a header, which then has to match
+struct Arch {
+    magic: [u8; 4],
+    input_set: u16,
+    input_size: usize,
+    hl_size: usize,
+    output_size: usize,
+    qa: i32,
+    qb: i32,
+    weight_bytes: usize,
+    // the value net struct is 64 byte aligned, so the raw file is padded to match
+    align: usize,
+}
+
+const HEADER_SIZE: usize = 64;
+const HEADER_VERSION: u16 = 1;
+
+const VALUE_ARCH: Arch = Arch {
+    magic: *b"ANVN",
+    input_set: 1,
+    input_size: 768 * 4,
+    hl_size: 1024,
+    output_size: 16,
+    qa: 256,
+    qb: 64,
+    weight_bytes: 2,
+    align: 64,
+};
+
+const POLICY_ARCH: Arch = Arch {
+    magic: *b"ANPN",
+    input_set: 1,
+    input_size: 768 * 4,
+    hl_size: 1024,
+    output_size: 1880,
+    qa: 128,
+    qb: 128,
+    weight_bytes: 1,
+    align: 1,
+};
+
+impl Arch {
+    // both nets are l1 weights, l1 biases, l2 weights, l2 biases
+    fn raw_size(&self) -> usize {
+        ((self.input_size * self.hl_size
+            + self.hl_size
+            + self.hl_size * self.output_size
+            + self.output_size)
+            * self.weight_bytes)
+            .next_multiple_of(self.align)
+    }
+
+    fn header(&self) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[0..4].copy_from_slice(&self.magic);
+        header[4..6].copy_from_slice(&HEADER_VERSION.to_le_bytes());
+        header[6..8].copy_from_slice(&self.input_set.to_le_bytes());
+        header[8..12].copy_from_slice(&(self.input_size as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&(self.hl_size as u32).to_le_bytes());
+        header[16..20].copy_from_slice(&(self.output_size as u32).to_le_bytes());
+        header[20..24].copy_from_slice(&self.qa.to_le_bytes());
+        header[24..28].copy_from_slice(&self.qb.to_le_bytes());
+        header
+    }
+}
+
+// where a net can come from, checked in this order:
+// - ANURA_VALUE_NET / ANURA_POLICY_NET, a path to the exact file to use
+// - the file already in src/nets, if it passes verification
+// - ANURA_NET_DIR, a directory containing <name>.vn / <name>.pn
+// - a download from the anura-nets releases, unless ANURA_OFFLINE is set
+struct NetSpec {
+    name: String,
+    sha256: String,
+    extension: &'static str,
+    arch: Arch,
+    dest: PathBuf,
+    file_var: &'static str,
+}
+
 fn main() {
-    // Print current working directory and environment for debugging
-    println!(
-        "Current directory: {}",
-        env::current_dir().unwrap().display()
-    );
-    println!(
-        "Cargo manifest dir: {}",
-        env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| "Not found".to_string())
+    let value = read_spec("value.txt", ".vn", VALUE_ARCH, "avn.vn", "ANURA_VALUE_NET");
+    let policy = read_spec(
+        "policy.txt",
+        ".pn",
+        POLICY_ARCH,
+        "apn.pn",
+        "ANURA_POLICY_NET",
     );
 
-    let value_network_name = match fs::read_to_string("value.txt") {
-        Ok(name) => name.trim().to_string(),
-        Err(e) => panic!("Error reading value.txt: {e}"),
+    provide_net(&value);
+    provide_net(&policy);
+    write_embedded(&value);
+    write_embedded(&policy);
+    write_magics();
+
+    println!("cargo:rerun-if-changed=value.txt");
+    println!("cargo:rerun-if-changed=policy.txt");
+    println!("cargo:rerun-if-changed={}", value.dest.display());
+    println!("cargo:rerun-if-changed={}", policy.dest.display());
+    for var in [
+        "ANURA_VALUE_NET",
+        "ANURA_POLICY_NET",
+        "ANURA_NET_DIR",
+        "ANURA_OFFLINE",
+    ] {
+        println!("cargo:rerun-if-env-changed={var}");
+    }
+}
+
+// value.txt / policy.txt hold the net name followed by its sha256, a net that can't be
+// verified never gets built in
+fn read_spec(
+    file: &str,
+    extension: &'static str,
+    arch: Arch,
+    dest: &str,
+    file_var: &'static str,
+) -> NetSpec {
+    let contents = fs::read_to_string(file).unwrap_or_else(|e| panic!("Error reading {file}: {e}"));
+    let mut parts = contents.split_whitespace();
+    let name = match parts.next() {
+        Some(name) => name.to_string(),
+        None => panic!("{file} contains no network name"),
     };
-    let policy_network_name = match fs::read_to_string("policy.txt") {
-        Ok(name) => name.trim().to_string(),
-        Err(e) => panic!("Error reading policy.txt: {e}"),
+    let dest = PathBuf::from("src/nets").join(dest);
+    let Some(hash) = parts.next() else {
+        // save a round trip when the right net is already there
+        let found = fs::read(&dest)
+            .map(|bytes| {
+                let hash = to_hex(&sha256(&bytes));
+                format!(", {} has sha256 {hash}", dest.display())
+            })
+            .unwrap_or_default();
+        panic!("{file} has no sha256 after {name}{found}");
     };
+    let hash = hash.trim_start_matches("sha256:").to_ascii_lowercase();
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        panic!("{file}: '{hash}' is not a sha256 hash");
+    }
 
-    if value_network_name.is_empty() {
-        panic!("value.txt contains no network name");
+    NetSpec {
+        name,
+        sha256: hash,
+        extension,
+        arch,
+        dest,
+        file_var,
     }
-    if policy_network_name.is_empty() {
-        panic!("policy.txt contains no network name");
+}
+
+fn provide_net(spec: &NetSpec) {
+    if let Ok(path) = env::var(spec.file_var) {
+        let path = PathBuf::from(path);
+        if let Err(e) = verify(spec, &path) {
+            panic!("{} is set but unusable: {e}", spec.file_var);
+        }
+        copy_net(&path, &spec.dest);
+        return;
     }
 
-    let value_path = PathBuf::from("src/nets/avn.vn".to_string());
-    let policy_path = PathBuf::from("src/nets/apn.pn".to_string());
+    if spec.dest.exists() {
+        match verify(spec, &spec.dest) {
+            Ok(()) => return,
+            Err(e) => println!(
+                "cargo:warning=ignoring existing {}: {e}",
+                spec.dest.display()
+            ),
+        }
+    }
 
-    // Print full paths for debugging
-    println!("Value network path: {}", value_path.display());
-    println!("Policy network path: {}", policy_path.display());
+    let file_name = format!("{}{}", spec.name, spec.extension);
+    if let Ok(dir) = env::var("ANURA_NET_DIR") {
+        let path = Path::new(&dir).join(&file_name);
+        if path.exists() {
+            if let Err(e) = verify(spec, &path) {
+                panic!("{e}");
+            }
+            copy_net(&path, &spec.dest);
+            return;
+        }
+        println!("cargo:warning={file_name} not found in ANURA_NET_DIR ({dir})");
+    }
 
-    download_network_if_not_exists(&value_network_name, &value_path, ".vn");
-    download_network_if_not_exists(&policy_network_name, &policy_path, ".pn");
+    if env::var_os("ANURA_OFFLINE").is_some() {
+        panic!(
+            "{file_name} is not available and ANURA_OFFLINE is set, \
+             point {} at the file or put it in ANURA_NET_DIR",
+            spec.file_var
+        );
+    }
 
-    println!("cargo:rerun-if-changed=value.txt");
-    println!("cargo:rerun-if-changed=policy.txt");
-    println!("cargo:rerun-if-changed={}", value_path.display());
-    println!("cargo:rerun-if-changed={}", policy_path.display());
-}
-fn download_network_if_not_exists(network_name: &str, dest_path: &Path, file_extension: &str) {
-    if dest_path.exists() {
-        println!(
-            "Network file {} already exists. Skipping download.",
-            dest_path.display()
+    // download next to the destination so a failure never clobbers what's there
+    let partial = PathBuf::from(format!("{}.part", spec.dest.display()));
+    if let Err(e) = download(spec, &partial) {
+        let _ = fs::remove_file(&partial);
+        panic!(
+            "{e}\nto build without network access point {} at {file_name} \
+             or put it in a directory given by ANURA_NET_DIR",
+            spec.file_var
         );
+    }
+    if let Err(e) = verify(spec, &partial) {
+        let _ = fs::remove_file(&partial);
+        panic!("downloaded net failed verification: {e}");
+    }
+    if let Err(e) = fs::rename(&partial, &spec.dest) {
+        panic!(
+            "couldn't move the downloaded net to {}: {e}",
+            spec.dest.display()
+        );
+    }
+}
+
+// the copy that gets embedded always has a header
+fn write_embedded(spec: &NetSpec) {
+    let mut bytes = fs::read(&spec.dest)
+        .unwrap_or_else(|e| panic!("couldn't read {}: {e}", spec.dest.display()));
+    if bytes.len() == spec.arch.raw_size() {
+        bytes.splice(0..0, spec.arch.header());
+    }
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR not set"));
+    let file_name = spec.dest.file_name().expect("net path has no file name");
+    let out_path = out_dir.join(file_name);
+    if let Err(e) = fs::write(&out_path, bytes) {
+        panic!("couldn't write {}: {e}", out_path.display());
+    }
+}
+
+fn copy_net(from: &Path, to: &Path) {
+    // same file, nothing to do
+    if fs::canonicalize(from).ok() == fs::canonicalize(to).ok() {
         return;
     }
+    if let Err(e) = fs::copy(from, to) {
+        panic!("couldn't copy {} to {}: {e}", from.display(), to.display());
+    }
+}
+
+// checks the size, the header and the hash
+fn verify(spec: &NetSpec, path: &Path) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|e| format!("couldn't read {}: {e}", path.display()))?;
+    let raw_size = spec.arch.raw_size();
+    if bytes.len() == raw_size + HEADER_SIZE {
+        if bytes[..HEADER_SIZE] != spec.arch.header() {
+            return Err(format!(
+                "{}'s header doesn't match the architecture this build expects",
+                path.display()
+            ));
+        }
+    } else if bytes.len() != raw_size {
+        return Err(format!(
+            "{} is {} bytes, a {} net should be {raw_size} or {} with a header \
+             (truncated or the wrong net?)",
+            path.display(),
+            bytes.len(),
+            spec.extension,
+            raw_size + HEADER_SIZE
+        ));
+    }
+
+    let hash = to_hex(&sha256(&bytes));
+    if hash != spec.sha256 {
+        return Err(format!(
+            "{} has sha256 {hash}, expected {} for {}",
+            path.display(),
+            spec.sha256,
+            spec.name
+        ));
+    }
+    Ok(())
+}
 
+fn download(spec: &NetSpec, dest: &Path) -> Result<(), String> {
     let url = format!(
-        "https://github.com/Vast342/anura-nets/releases/download/{network_name}/{network_name}{file_extension}"
+        "https://github.com/Vast342/anura-nets/releases/download/{0}/{0}{1}",
+        spec.name, spec.extension
     );
+    let dest = dest.to_str().expect("non utf-8 net path");
 
-    // Print detailed URL information
-    println!("Attempting to download:");
-    println!("Network Name: {network_name}");
-    println!("Full URL: {url}");
-
-    // Try curl first, with SSL verification disabled
-    let curl_result = Command::new("curl")
-        .args([
-            "-sL", // Silent mode, follow redirects
-            "-f",  // Fail silently on server errors
-            "-k",  // Allow insecure server connections
-            "-v",  // Verbose output
-            "-o",
-            dest_path.to_str().unwrap(),
-            &url,
-        ])
-        .output();
-
-    match curl_result {
-        Ok(output) => {
-            if output.status.success() {
-                println!(
-                    "Downloaded {} to {} using curl",
-                    network_name,
-                    dest_path.display()
-                );
-                return;
-            } else {
-                eprintln!("Curl download failed");
-                eprintln!("Stdout: {}", String::from_utf8_lossy(&output.stdout));
-                eprintln!("Stderr: {}", String::from_utf8_lossy(&output.stderr));
-            }
+    // curl first, then wget, both with certificate checks on
+    let attempts: [(&str, Vec<&str>); 2] = [
+        ("curl", vec!["-sSLf", "-o", dest, &url]),
+        ("wget", vec!["-q", "-O", dest, &url]),
+    ];
+    let mut errors = vec![];
+    for (program, args) in attempts {
+        match Command::new(program).args(&args).output() {
+            Ok(output) if output.status.success() => return Ok(()),
+            Ok(output) => errors.push(format!(
+                "{program} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(e) => errors.push(format!("couldn't run {program}: {e}")),
         }
-        Err(e) => {
-            eprintln!("Failed to execute curl: {e}");
+    }
+
+    Err(format!("failed to download {url}\n{}", errors.join("\n")))
+}
+
+// magic bitboards, for targets without a (fast) pext
+// fixed shifts, so the tables have the same size and layout as the pext ones in src/movegen/tables
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+const ROOK_BITS: u32 = 12;
+const BISHOP_BITS: u32 = 9;
+
+fn write_magics() {
+    // only needed when slideys.rs doesn't use pext
+    let features = env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default();
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    let pext = arch == "x86_64" && features.split(',').any(|feature| feature == "bmi2");
+    if pext && env::var_os("CARGO_FEATURE_MAGICS").is_none() {
+        return;
+    }
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("no OUT_DIR"));
+    let mut seed = 0x2545_f491_4f6c_dd1d;
+    for (name, directions, bits) in [
+        ("rook", ROOK_DIRECTIONS, ROOK_BITS),
+        ("bishop", BISHOP_DIRECTIONS, BISHOP_BITS),
+    ] {
+        let mut magics = Vec::with_capacity(64 * 8);
+        let mut table = Vec::with_capacity(64 << bits << 3);
+        for sq in 0..64 {
+            let (magic, attacks) = find_magic(sq, &directions, bits, &mut seed);
+            magics.extend_from_slice(&magic.to_le_bytes());
+            for attack in attacks {
+                table.extend_from_slice(&attack.to_le_bytes());
+            }
         }
+        fs::write(out_dir.join(format!("{name}_magics.bin")), magics)
+            .expect("failed to write magics");
+        fs::write(out_dir.join(format!("{name}_attacks.bin")), table)
+            .expect("failed to write magic attack table");
     }
+}
 
-    // If curl fails, try wget with SSL verification disabled
-    let wget_result = Command::new("wget")
-        .args([
-            "--no-check-certificate", // Disable SSL verification
-            "-O",
-            dest_path.to_str().unwrap(),
-            &url,
-        ])
-        .output();
-
-    match wget_result {
-        Ok(output) => {
-            if output.status.success() {
-                println!(
-                    "Downloaded {} to {} using wget",
-                    network_name,
-                    dest_path.display()
-                );
-                return;
-            } else {
-                eprintln!("Wget download failed");
-                eprintln!("Stdout: {}", String::from_utf8_lossy(&output.stdout));
-                eprintln!("Stderr: {}", String::from_utf8_lossy(&output.stderr));
+// attacks from sq along the given directions, stopping at (and including) blockers
+fn slider_attacks(sq: i32, directions: &[(i32, i32)], occupied: u64) -> u64 {
+    let mut attacks = 0;
+    for &(file_step, rank_step) in directions {
+        let (mut file, mut rank) = (sq % 8 + file_step, sq / 8 + rank_step);
+        while (0..8).contains(&file) && (0..8).contains(&rank) {
+            let bit = 1 << (rank * 8 + file);
+            attacks |= bit;
+            if occupied & bit != 0 {
+                break;
             }
+            file += file_step;
+            rank += rank_step;
         }
-        Err(e) => {
-            eprintln!("Failed to execute wget: {e}");
+    }
+    attacks
+}
+
+// the squares whose occupancy matters, the last square of each ray never blocks anything
+fn slider_mask(sq: i32, directions: &[(i32, i32)]) -> u64 {
+    let mut mask = 0;
+    for &(file_step, rank_step) in directions {
+        let (mut file, mut rank) = (sq % 8 + file_step, sq / 8 + rank_step);
+        while (0..8).contains(&(file + file_step)) && (0..8).contains(&(rank + rank_step)) {
+            mask |= 1 << (rank * 8 + file);
+            file += file_step;
+            rank += rank_step;
         }
     }
+    mask
+}
+
+// tries sparse random numbers until one maps every occupancy without a harmful collision
+fn find_magic(sq: i32, directions: &[(i32, i32)], bits: u32, seed: &mut u64) -> (u64, Vec<u64>) {
+    let mask = slider_mask(sq, directions);
+    let mut occupancies = vec![];
+    let mut subset: u64 = 0;
+    loop {
+        occupancies.push((subset, slider_attacks(sq, directions, subset)));
+        subset = subset.wrapping_sub(mask) & mask;
+        if subset == 0 {
+            break;
+        }
+    }
+
+    let mut table = vec![0; 1 << bits];
+    let mut used = vec![false; 1 << bits];
+    'search: loop {
+        let magic = next_random(seed) & next_random(seed) & next_random(seed);
+        if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+            continue;
+        }
+        used.fill(false);
+        for &(occupied, attacks) in &occupancies {
+            let index = (occupied.wrapping_mul(magic) >> (64 - bits)) as usize;
+            if used[index] && table[index] != attacks {
+                continue 'search;
+            }
+            used[index] = true;
+            table[index] = attacks;
+        }
+        return (magic, table);
+    }
+}
 
-    // If both methods fail, panic with detailed error
-    let _ = fs::remove_file(dest_path);
-    panic!("Failed to download network file. URL: {url}. Check network connectivity and URL.");
+// xorshift, the magics only have to be deterministic
+fn next_random(seed: &mut u64) -> u64 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    *seed
 }
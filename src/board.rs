@@ -24,16 +24,19 @@ pub static SQUARE_NAMES: [&str; 65] = [
     "None",
 ];
 
-pub const ROOK_RIGHT_MASKS: [u8; 64] = [
-    0b1101, 255, 255, 255, 255, 255, 255, 0b1110, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    0b0111, 255, 255, 255, 255, 255, 255, 0b1011,
-];
 pub const KING_RIGHT_MASKS: [u8; 2] = [0b0011, 0b1100];
+// where the king and rook end up for each castling right (K, Q, k, q), no matter where they started
+pub const CASTLING_DESTINATIONS: [(Square, Square); 4] = [
+    (Square(6), Square(5)),
+    (Square(2), Square(3)),
+    (Square(62), Square(61)),
+    (Square(58), Square(59)),
+];
+
+use std::fmt;
 
 use crate::{
-    hash::{zobrist_ctm, zobrist_psq},
+    hash::{zobrist_castling, zobrist_ctm, zobrist_ep, zobrist_psq},
     movegen::{
         lookups::DIRECTIONAL_OFFSETS,
         others::{get_king_attacks, get_knight_attacks},
@@ -47,7 +50,7 @@ use crate::{
         moves::{Flag, Move},
         piece::{Colors, Piece, Types},
         square::Square,
-        MoveList,
+        MoveList, PolicyList,
     },
 };
 
@@ -60,6 +63,8 @@ pub struct Position {
     pub ep_index: Square,
     pub hm_clock: u8,
     pub castling: u8,
+    // the rook each castling right (K, Q, k, q) castles with
+    pub castling_rooks: [Square; 4],
     checkers: Bitboard,
     diago_pin_mask: Bitboard,
     ortho_pin_mask: Bitboard,
@@ -82,6 +87,7 @@ impl Position {
             ep_index: epsq,
             hm_clock: hmc,
             castling: ca,
+            castling_rooks: [Square::INVALID; 4],
             checkers: Bitboard::EMPTY,
             diago_pin_mask: Bitboard::EMPTY,
             ortho_pin_mask: Bitboard::EMPTY,
@@ -138,10 +144,53 @@ impl Position {
         self.colors[color as usize] & self.pieces[piece as usize]
     }
 
+    // the castling rights that are kept when a rook leaves (or is captured on) sq
+    #[must_use]
+    pub fn rook_right_mask(&self, sq: Square) -> u8 {
+        let mut mask = 0b1111;
+        for (right, &rook) in self.castling_rooks.iter().enumerate() {
+            if rook == sq {
+                mask &= !(1 << right);
+            }
+        }
+        mask
+    }
+
+    #[must_use]
+    pub const fn hash(&self) -> u64 {
+        self.hash
+    }
+
     pub fn switch_color(&mut self) {
         self.hash ^= zobrist_ctm();
     }
 
+    // the full key from scratch, make_move keeps it up to date incrementally
+    #[must_use]
+    pub fn compute_hash(&self, ctm: u8) -> u64 {
+        let mut hash = 0;
+        let mut occ = self.occupied();
+        while occ.is_not_empty() {
+            let sq = Square(occ.pop_lsb());
+            hash ^= zobrist_psq(self.piece_on_square(sq), sq);
+        }
+        if ctm == 1 {
+            hash ^= zobrist_ctm();
+        }
+        hash ^= zobrist_castling(self.castling);
+        if self.ep_index != Square::INVALID && self.ep_capturable(self.ep_index, ctm) {
+            hash ^= zobrist_ep(self.ep_index);
+        }
+        hash
+    }
+
+    // an ep square is only kept when a pawn of the side to move could take on it, otherwise
+    // it would split identical positions in the hash and hide repetitions
+    fn ep_capturable(&self, ep: Square, ctm: u8) -> bool {
+        (get_pawn_attacks_lookup(ep, 1 - ctm) & self.colored_piece(Types::Pawn as u8, ctm))
+            .is_not_empty()
+    }
+
     pub fn bb(&self) -> [u64; 8] {
         let mut thing = [0; 8];
         thing[0] = self.colors[1].0;
@@ -217,6 +266,53 @@ impl Position {
     }
 }
 
+// everything load_fen rejects, anything it accepts is safe to search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    MissingField(&'static str),
+    RankCount(usize),
+    RankLength(u8),
+    InvalidCharacter(char),
+    KingCount(u8, u32),
+    PawnOnBackRank(Square),
+    InvalidSideToMove(String),
+    InvalidCastling(char),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidMoveCount(String),
+    OpponentInCheck,
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let color_name = |color: &u8| if *color == 1 { "white" } else { "black" };
+        match self {
+            Self::MissingField(field) => write!(f, "fen has no {field}"),
+            Self::RankCount(count) => write!(f, "fen has {count} ranks instead of 8"),
+            Self::RankLength(rank) => write!(f, "rank {} of the fen isn't 8 squares", rank + 1),
+            Self::InvalidCharacter(c) => write!(f, "invalid character in fen: {c}"),
+            Self::KingCount(color, count) => {
+                write!(f, "{} has {count} kings instead of 1", color_name(color))
+            }
+            Self::PawnOnBackRank(sq) => {
+                write!(
+                    f,
+                    "pawn on the back rank at {}",
+                    SQUARE_NAMES[sq.0 as usize]
+                )
+            }
+            Self::InvalidSideToMove(token) => write!(f, "invalid side to move in fen: {token}"),
+            Self::InvalidCastling(c) => write!(f, "invalid castling rights in fen: {c}"),
+            Self::InvalidEnPassant(token) => write!(f, "invalid en passant square in fen: {token}"),
+            Self::InvalidHalfmoveClock(token) => {
+                write!(f, "invalid halfmove clock in fen: {token}")
+            }
+            Self::InvalidMoveCount(token) => write!(f, "invalid move count in fen: {token}"),
+            Self::OpponentInCheck => write!(f, "the side not to move is in check"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Board {
     pub states: Vec<Position>,
@@ -286,15 +382,22 @@ impl Board {
     }
 
     #[allow(clippy::cast_possible_truncation)]
-    pub fn load_fen(&mut self, fen: &str) {
+    pub fn load_fen(&mut self, fen: &str) -> Result<(), FenError> {
         let mut state: Position = Position::empty();
         let mut fen_split = fen.split_ascii_whitespace();
         // first token: position
-        let mut token = fen_split.next().expect("no position?");
-        let mut ranks = token.rsplit('/');
-        let mut i: Square = Square(0);
-        for rank in ranks.by_ref() {
+        let mut token = fen_split.next().ok_or(FenError::MissingField("position"))?;
+        let ranks: Vec<&str> = token.rsplit('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::RankCount(ranks.len()));
+        }
+        for (rank_index, rank) in ranks.iter().enumerate() {
+            let mut i = Square::from_rf(rank_index as u8, 0);
+            let rank_end = i.0 + 8;
             for c in rank.chars() {
+                if i.0 >= rank_end {
+                    return Err(FenError::RankLength(rank_index as u8));
+                }
                 match c {
                     'p' => {
                         state.add_piece(
@@ -382,59 +485,143 @@ impl Board {
                         state.king_sqs[1] = i;
                         i += Square(1);
                     }
-                    _ => i += Square(c.to_digit(10).expect("invalid character in fen") as u8),
+                    _ => {
+                        let empties = c
+                            .to_digit(10)
+                            .filter(|empties| (1..=8).contains(empties))
+                            .ok_or(FenError::InvalidCharacter(c))?;
+                        i += Square(empties as u8);
+                    }
                 }
             }
+            if i.0 != rank_end {
+                return Err(FenError::RankLength(rank_index as u8));
+            }
+        }
+        for color in [Colors::White as u8, Colors::Black as u8] {
+            let kings = state.colored_piece(Types::King as u8, color).popcount();
+            if kings != 1 {
+                return Err(FenError::KingCount(color, kings));
+            }
+        }
+        let back_ranks = Bitboard::from_rank(0) | Bitboard::from_rank(7);
+        let back_rank_pawns = state.pieces[Types::Pawn as usize] & back_ranks;
+        if back_rank_pawns.is_not_empty() {
+            return Err(FenError::PawnOnBackRank(Square(back_rank_pawns.lsb())));
         }
 
         // second token: color to move
-        token = fen_split.next().expect("no ctm?");
-        self.ctm = u8::from(token == "w");
-        if self.ctm == 1 {
+        token = fen_split
+            .next()
+            .ok_or(FenError::MissingField("side to move"))?;
+        let ctm = match token {
+            "w" => 1,
+            "b" => 0,
+            _ => return Err(FenError::InvalidSideToMove(token.to_owned())),
+        };
+        if ctm == 1 {
             state.switch_color()
         };
 
         // third token: castling rights
-        token = fen_split.next().expect("no castling rights?");
+        // KQkq take the outermost rook on that side, Shredder/X-FEN files (AHah) name the rook directly
+        token = fen_split
+            .next()
+            .ok_or(FenError::MissingField("castling rights"))?;
         for c in token.chars() {
-            match c {
-                'K' => state.castling |= 1,
-                'Q' => state.castling |= 2,
-                'k' => state.castling |= 4,
-                'q' => state.castling |= 8,
-                '-' | ' ' => (),
-                _ => panic!("invalid castling rights (Anura doesn't support frc), you gave {c}"),
+            if c == '-' {
+                continue;
+            }
+            let color = u8::from(c.is_ascii_uppercase());
+            let back_rank = if color == 1 { 0 } else { 7 };
+            let king = state.king_sqs[color as usize];
+            let is_rook = |file: &u8| {
+                state.piece_on_square(Square::from_rf(back_rank, *file))
+                    == Piece::new_unchecked(Types::Rook as u8, color)
+            };
+            let file = match c.to_ascii_lowercase() {
+                'k' => (king.file() + 1..8).rev().find(is_rook),
+                'q' => (0..king.file()).find(is_rook),
+                file @ 'a'..='h' => Some(file as u8 - b'a').filter(is_rook),
+                _ => None,
             }
+            .filter(|_| king.rank() == back_rank)
+            .ok_or(FenError::InvalidCastling(c))?;
+            let right = 2 * (1 - color) + u8::from(file < king.file());
+            state.castling |= 1 << right;
+            state.castling_rooks[right as usize] = Square::from_rf(back_rank, file);
         }
+        state.hash ^= zobrist_castling(state.castling);
 
         // fourth token: en passant
-        token = fen_split.next().expect("no en passant index?");
+        token = fen_split
+            .next()
+            .ok_or(FenError::MissingField("en passant square"))?;
         if token == "-" {
             state.ep_index = Square(64);
         } else {
-            for i in 0..63 {
-                if token == SQUARE_NAMES[i as usize] {
-                    state.ep_index = Square(i);
-                    break;
-                };
+            let ep = SQUARE_NAMES[..64]
+                .iter()
+                .position(|&name| name == token)
+                .map(|ep| Square(ep as u8))
+                .ok_or_else(|| FenError::InvalidEnPassant(token.to_owned()))?;
+            // the pawn that just double pushed has to be in front of the ep square, with nothing left behind it
+            let ep_rank = if ctm == 1 { 5 } else { 2 };
+            if ep.rank() != ep_rank {
+                return Err(FenError::InvalidEnPassant(token.to_owned()));
+            }
+            let (pushed_to, pushed_from) = if ctm == 1 {
+                (Square(ep.0 - 8), Square(ep.0 + 8))
+            } else {
+                (Square(ep.0 + 8), Square(ep.0 - 8))
+            };
+            let pawn = Piece::new_unchecked(Types::Pawn as u8, 1 - ctm);
+            if state.piece_on_square(pushed_to) != pawn
+                || state.piece_on_square(ep) != Piece::NONE
+                || state.piece_on_square(pushed_from) != Piece::NONE
+            {
+                return Err(FenError::InvalidEnPassant(token.to_owned()));
+            }
+            if state.ep_capturable(ep, ctm) {
+                state.ep_index = ep;
+                state.hash ^= zobrist_ep(state.ep_index);
             }
         }
 
         // here on out is optional:
         // fifth token: half move clock
+        let mut ply = None;
         let mut token_option = fen_split.next();
-        if token_option.is_some() {
-            state.hm_clock = 0;
+        if let Some(token) = token_option {
+            let hm_clock: u8 = token
+                .parse()
+                .map_err(|_| FenError::InvalidHalfmoveClock(token.to_owned()))?;
+            // anything past 100 is drawn already, and make_move can't overflow it from here
+            state.hm_clock = hm_clock.min(100);
             // sixth token: ply count
             token_option = fen_split.next();
-            self.ply = token_option
-                .expect("why would you have a 5th token but not a 6th")
-                .parse()
-                .unwrap();
+            if let Some(token) = token_option {
+                ply = Some(
+                    token
+                        .parse()
+                        .map_err(|_| FenError::InvalidMoveCount(token.to_owned()))?,
+                );
+            }
+        }
+
+        // the side that just moved can't have left its king in check
+        let opponent_king = state.colored_piece(Types::King as u8, 1 - ctm);
+        if (state.threats_by(ctm) & opponent_king).is_not_empty() {
+            return Err(FenError::OpponentInCheck);
         }
 
+        self.ctm = ctm;
+        if let Some(ply) = ply {
+            self.ply = ply;
+        }
         self.states.push(state);
         self.update_pins_and_checkers();
+        Ok(())
     }
 
     pub fn get_moves(&self, list: &mut MoveList) {
@@ -450,35 +637,34 @@ impl Board {
         let empties: Bitboard = !occ;
 
         if (state.castling & KING_RIGHT_MASKS[1 - self.ctm as usize]) != 0 && num_checkers == 0 {
-            if self.ctm == 1 {
-                if (state.castling & 1) != 0
-                    && (occ & Bitboard(0x60)).is_empty()
-                    && !self.square_attacked(Square(5))
-                    && !self.square_attacked(Square(6))
-                {
-                    list.push(Move::new_unchecked(4, 6, Flag::WKCastle as u8));
+            let king = state.king_sqs[self.ctm as usize];
+            // kingside then queenside
+            let first_right = 2 * (1 - self.ctm as usize);
+            for (right, &(king_to, rook_to)) in CASTLING_DESTINATIONS
+                .iter()
+                .enumerate()
+                .skip(first_right)
+                .take(2)
+            {
+                if state.castling & (1 << right) == 0 {
+                    continue;
                 }
-                if (state.castling & 2) != 0
-                    && (occ & Bitboard(0xE)).is_empty()
-                    && !self.square_attacked(Square(3))
-                    && !self.square_attacked(Square(2))
-                {
-                    list.push(Move::new_unchecked(4, 2, Flag::WQCastle as u8));
+                let rook = state.castling_rooks[right];
+                // everything the king and rook pass over has to be empty (apart from themselves),
+                // and nothing the king passes over can be attacked, looking through the rook
+                let without = occ ^ Bitboard::from_square(king) ^ Bitboard::from_square(rook);
+                let mut king_path = ray_between(king, king_to) | Bitboard::from_square(king_to);
+                let rook_path = ray_between(rook, rook_to) | Bitboard::from_square(rook_to);
+                if ((king_path | rook_path) & without).is_not_empty() {
+                    continue;
                 }
-            } else {
-                if (state.castling & 4) != 0
-                    && (occ & Bitboard(0x6000_0000_0000_0000)).is_empty()
-                    && !self.square_attacked(Square(61))
-                    && !self.square_attacked(Square(62))
-                {
-                    list.push(Move::new_unchecked(60, 62, Flag::BKCastle as u8));
+                let mut attacked = false;
+                while king_path.is_not_empty() && !attacked {
+                    attacked = self.square_attacked_occ(Square(king_path.pop_lsb()), without);
                 }
-                if (state.castling & 8) != 0
-                    && (occ & Bitboard(0x0E00_0000_0000_0000)).is_empty()
-                    && !self.square_attacked(Square(59))
-                    && !self.square_attacked(Square(58))
-                {
-                    list.push(Move::new_unchecked(60, 58, Flag::BQCastle as u8));
+                if !attacked {
+                    // stored as the king taking its own rook
+                    list.push(Move::new_unchecked(king.0, rook.0, right as u8 + 1));
                 }
             }
         }
@@ -724,8 +910,14 @@ impl Board {
         let to = mov.to();
         let to_square = Square(to);
         let piece = state.piece_on_square(from_square);
-        let victim = state.piece_on_square(to_square);
         let flag = mov.flag();
+        // castling moves are the king taking its own rook
+        let is_castling = (Flag::WKCastle..=Flag::BQCastle).contains(&flag);
+        let victim = if is_castling {
+            Piece::NONE
+        } else {
+            state.piece_on_square(to_square)
+        };
         let is_capture = victim.piece() != Types::None as u8;
 
         state.hm_clock += 1;
@@ -734,60 +926,57 @@ impl Board {
         }
 
         if piece.piece() == Types::King as u8 {
-            state.king_sqs[self.ctm as usize] = to_square;
+            state.king_sqs[self.ctm as usize] = if is_castling {
+                CASTLING_DESTINATIONS[mov.flag() as usize - 1].0
+            } else {
+                to_square
+            };
         }
 
+        let old_castling = state.castling;
         if state.castling & KING_RIGHT_MASKS[1 - self.ctm as usize] != 0 {
             match piece.piece() {
-                3 => state.castling &= ROOK_RIGHT_MASKS[from as usize],
+                3 => state.castling &= state.rook_right_mask(from_square),
                 5 => state.castling &= KING_RIGHT_MASKS[self.ctm as usize],
                 _ => (),
             }
         }
 
         if victim.piece() == Types::Rook as u8 {
-            state.castling &= ROOK_RIGHT_MASKS[to as usize];
+            state.castling &= state.rook_right_mask(to_square);
         }
+        state.hash ^= zobrist_castling(old_castling) ^ zobrist_castling(state.castling);
 
-        state.ep_index = Square::INVALID;
+        if state.ep_index != Square::INVALID {
+            state.hash ^= zobrist_ep(state.ep_index);
+            state.ep_index = Square::INVALID;
+        }
 
         // pretty much inlined state.move_piece so that I can save an add+remove+add shenanigan for promotions
         if is_capture {
             state.remove_piece(to_square, victim);
         }
         state.remove_piece(from_square, piece);
-        if flag < Flag::KnightPromo {
+        if flag < Flag::KnightPromo && !is_castling {
             state.add_piece(to_square, piece);
         }
 
         match flag {
             Flag::Normal => (),
-            Flag::WKCastle => state.move_piece(
-                Square(7),
-                Piece::new_unchecked(Types::Rook as u8, Colors::White as u8),
-                Square(5),
-                Piece(Types::None as u8),
-            ),
-            Flag::WQCastle => state.move_piece(
-                Square(0),
-                Piece::new_unchecked(Types::Rook as u8, Colors::White as u8),
-                Square(3),
-                Piece(Types::None as u8),
-            ),
-            Flag::BKCastle => state.move_piece(
-                Square(63),
-                Piece::new_unchecked(Types::Rook as u8, Colors::Black as u8),
-                Square(61),
-                Piece(Types::None as u8),
-            ),
-            Flag::BQCastle => state.move_piece(
-                Square(56),
-                Piece::new_unchecked(Types::Rook as u8, Colors::Black as u8),
-                Square(59),
-                Piece(Types::None as u8),
-            ),
+            // in frc the king or rook can end up where the other one started, so both leave first
+            Flag::WKCastle | Flag::WQCastle | Flag::BKCastle | Flag::BQCastle => {
+                let (king_to, rook_to) = CASTLING_DESTINATIONS[mov.flag() as usize - 1];
+                let rook = Piece::new_unchecked(Types::Rook as u8, self.ctm);
+                state.remove_piece(to_square, rook);
+                state.add_piece(king_to, piece);
+                state.add_piece(rook_to, rook);
+            }
             Flag::DoublePush => {
-                state.ep_index = Square((to as i8 + DIRECTIONAL_OFFSETS[self.ctm as usize]) as u8)
+                let ep = Square((to as i8 + DIRECTIONAL_OFFSETS[self.ctm as usize]) as u8);
+                if state.ep_capturable(ep, 1 - self.ctm) {
+                    state.ep_index = ep;
+                    state.hash ^= zobrist_ep(state.ep_index);
+                }
             }
             Flag::EnPassant => state.remove_piece(
                 Square((to as i8 + DIRECTIONAL_OFFSETS[self.ctm as usize]) as u8),
@@ -813,6 +1002,11 @@ impl Board {
         self.ply += 1;
         self.ctm = 1 - self.ctm;
         state.switch_color();
+        debug_assert_eq!(
+            state.hash,
+            state.compute_hash(self.ctm),
+            "incremental hash doesn't match, {mov}"
+        );
         self.update_pins_and_checkers();
     }
 
@@ -900,12 +1094,18 @@ impl Board {
         policy.load_position(state, self.ctm);
     }
 
-    pub fn get_policy(&self, mov: Move, policy: &mut PolicyAccumulator) -> f32 {
-        policy.get_score(
-            mov,
+    pub fn get_policies(
+        &self,
+        moves: &[Move],
+        policy: &PolicyAccumulator,
+        scores: &mut PolicyList,
+    ) {
+        policy.get_scores(
+            moves,
             self.ctm,
             self.current_state().king_sqs[self.ctm as usize],
-        )
+            scores,
+        );
     }
 
     #[must_use]
@@ -955,23 +1155,34 @@ impl Board {
             1 => "w",
             _ => panic!("invalid ctm"),
         };
-        // castling rights
+        // castling rights, X-FEN style: KQkq unless there's another rook further out on that side
         fen += " ";
         let mut thing_added = false;
-        if (state.castling & 1) != 0 {
-            fen += "K";
-            thing_added = true;
-        }
-        if (state.castling & 2) != 0 {
-            fen += "Q";
-            thing_added = true;
-        }
-        if (state.castling & 4) != 0 {
-            fen += "k";
-            thing_added = true;
-        }
-        if (state.castling & 8) != 0 {
-            fen += "q";
+        for right in 0..4 {
+            if (state.castling & (1 << right)) == 0 {
+                continue;
+            }
+            let rook = state.castling_rooks[right];
+            let color = u8::from(right < 2);
+            let outer_files = if right % 2 == 0 {
+                rook.file() + 1..8
+            } else {
+                0..rook.file()
+            };
+            let outer_rook = outer_files.into_iter().any(|file| {
+                state.piece_on_square(Square::from_rf(rook.rank(), file))
+                    == Piece::new_unchecked(Types::Rook as u8, color)
+            });
+            let c = match (outer_rook, right % 2 == 0) {
+                (true, _) => (b'a' + rook.file()) as char,
+                (false, true) => 'k',
+                (false, false) => 'q',
+            };
+            fen.push(if color == 1 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            });
             thing_added = true;
         }
         if !thing_added {
@@ -989,7 +1200,7 @@ impl Board {
         // nevermind i do now
         if complete {
             fen += " ";
-            fen += &(state.hm_clock / 2).to_string();
+            fen += &state.hm_clock.to_string();
             fen += " ";
             fen += &self.ply.to_string();
         }
@@ -24,13 +24,17 @@
 
 // make datagen
 // ./anura datagen 12 ../AnuraData/Text/ 124598902
+// games get adjudicated by the tablebases when given a syzygy path
+// ./anura datagen 12 ../AnuraData/Text/ 124598902 --syzygy ../syzygy
 // fix yo dang draw detection, `5R2/5Qp1/P6k/7p/8/2P4P/5PP1/6K1 w - - | 1033 | 0.5` is bad
 
 use crate::{
     board::{Board, Position},
+    mcts::node::GameResult,
     mcts::search::Engine,
     mcts::search::EVAL_SCALE,
     mcts::time::Limiters,
+    syzygy::{load_tablebases, tablebases},
     tunable::Tunables,
     types::MoveList,
 };
@@ -56,6 +60,19 @@ pub type Datapoint = MontyFormat;
 pub fn datagen_main(args: Vec<String>) {
     let thread_count: usize = args[2].parse().expect("invalid thread count");
     println!("generating data on {thread_count} threads");
+    if let Some(i) = args.iter().position(|arg| arg == "--syzygy") {
+        let Some(path) = args.get(i + 1) else {
+            println!("--syzygy needs a path");
+            return;
+        };
+        match load_tablebases(path) {
+            Ok(count) => println!("adjudicating with {count} tablebases"),
+            Err(e) => {
+                println!("couldn't load tablebases: {e}");
+                return;
+            }
+        }
+    }
     let draw_count = Arc::new(AtomicU64::new(0));
     let game_count = Arc::new(AtomicU64::new(0));
     let pos_count = Arc::new(AtomicU64::new(0));
@@ -103,7 +120,9 @@ fn thread_function(
     tunables: &Tunables,
 ) {
     let mut board: Board = Board::default();
-    board.load_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    board
+        .load_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+        .expect("startpos is a valid fen");
     let this_directory = directory + "thread" + &thread_id.to_string() + ".bin";
     let mut writer = BufWriter::new(File::create(this_directory).expect("couldn't create file"));
     loop {
@@ -189,8 +208,19 @@ fn run_game(datapoints: &mut Vec<Datapoint>, mut board: Board, params: &Tunables
             }, game_nodes, game_searches);
         }
 
+        // no need to play it out once the tablebases know the result
+        if let Some(result) = tablebases().and_then(|tb| tb.probe_wdl(&mut board)) {
+            datapoints.push(game);
+            let result = match result {
+                GameResult::Win => 2 * board.ctm,
+                GameResult::Loss => 2 - 2 * board.ctm,
+                _ => 1,
+            };
+            return (result, game_nodes, game_searches);
+        }
+
         let (mov, score, mut visit_points) = engine.datagen_search(board.clone(), params, limiters);
-        game_nodes += engine.nodes as u64;
+        game_nodes += engine.nodes();
         game_searches += 1;
 
         board.make_move(mov);
@@ -297,7 +327,7 @@ use rand::SeedableRng;
 
 fn get_opening<R: Rng>(start_fen: &str, rng: &mut R) -> Option<String> {
     let mut board = Board::default();
-    board.load_fen(start_fen);
+    board.load_fen(start_fen).expect("invalid opening fen");
     // 8 random moves
     for _ in 0..8 {
         // generate the moves
@@ -23,13 +23,22 @@ use crate::{
 
 const PIECE_SQUARE_SIZE: usize = 14 * 64;
 const CTM_SIZE: usize = 1;
+// one per combination of rights
+const CASTLING_SIZE: usize = 16;
+// one per file
+const EP_SIZE: usize = 8;
 const PIECE_SQUARE_STRIDE: usize = 0;
 const CTM_STRIDE: usize = PIECE_SQUARE_STRIDE + PIECE_SQUARE_SIZE;
+const CASTLING_STRIDE: usize = CTM_STRIDE + CTM_SIZE;
+const EP_STRIDE: usize = CASTLING_STRIDE + CASTLING_SIZE;
 
-const TOTAL_SIZE: usize = PIECE_SQUARE_SIZE + CTM_SIZE;
+const TOTAL_SIZE: usize = PIECE_SQUARE_SIZE + CTM_SIZE + CASTLING_SIZE + EP_SIZE;
 
 const ZOBRIST_VALUES: [u64; TOTAL_SIZE] = fill_array();
 
+// bump whenever the keys or what goes into them change, anything saved by hash goes stale
+pub const KEY_VERSION: u32 = 1;
+
 pub fn zobrist_psq(piece: Piece, sq: Square) -> u64 {
     ZOBRIST_VALUES[PIECE_SQUARE_STRIDE + sq.0 as usize * 14 + piece.0 as usize]
 }
@@ -37,3 +46,11 @@ pub fn zobrist_psq(piece: Piece, sq: Square) -> u64 {
 pub fn zobrist_ctm() -> u64 {
     ZOBRIST_VALUES[CTM_STRIDE]
 }
+
+pub fn zobrist_castling(rights: u8) -> u64 {
+    ZOBRIST_VALUES[CASTLING_STRIDE + rights as usize]
+}
+
+pub fn zobrist_ep(sq: Square) -> u64 {
+    ZOBRIST_VALUES[EP_STRIDE + sq.file() as usize]
+}
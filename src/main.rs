@@ -32,6 +32,7 @@ pub mod nets;
 pub mod perft;
 pub mod prng;
 pub mod rays;
+pub mod syzygy;
 pub mod tunable;
 pub mod types;
 pub mod uci;
@@ -56,7 +57,13 @@ fn main() {
             #[cfg(feature = "datagen")]
             datagen_main(args);
         } else if args[1] == "perftsuite" && cfg!(feature = "perftsuite") {
-            manager.perft_suite();
+            if let Err(e) = manager.perft_suite(&args[1..].join(" ")) {
+                eprintln!("{e}");
+            }
+        } else if args[1] == "addheader" && args.len() > 3 {
+            if let Err(e) = nets::add_header(&args[2], &args[3]) {
+                eprintln!("{e}");
+            }
         } else if args[1].split_ascii_whitespace().collect::<Vec<&str>>()[0] == "genfens" {
             #[cfg(feature = "datagen")]
             gen_fens(args);
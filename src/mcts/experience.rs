@@ -0,0 +1,211 @@
+/*
+    Anura
+    Copyright (C) 2025 Joseph Pasfield
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// the experience file remembers the root visits and scores of past searches, so a position
+// that comes up again (like the same openings in a test match) doesn't start from nothing
+// records get appended as searches finish, when loading the last record for a position wins
+// the file gets rewritten with only the live records on load, and whenever the replaced ones
+// outnumber them, so it doesn't keep growing over a long match
+// the file starts with a magic and the version of the position keys, anything else is left alone
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    sync::Mutex,
+};
+
+use crate::{hash::KEY_VERSION, types::moves::Move};
+
+const MAGIC: &[u8; 4] = b"ANEX";
+// hash, then the move count, then each move
+const RECORD_HEADER_SIZE: usize = 9;
+const MOVE_SIZE: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExperienceMove {
+    pub mov: Move,
+    pub visits: u32,
+    // average score from the point of view of the side playing the move, books don't have one
+    pub score: Option<f32>,
+}
+
+struct Experience {
+    path: String,
+    positions: HashMap<u64, Vec<ExperienceMove>>,
+    // records in the file, including replaced ones
+    records: usize,
+    writer: BufWriter<File>,
+}
+
+impl Experience {
+    // writes the live records to a new file and swaps it in, so a crash halfway through
+    // leaves the old one intact
+    fn compact(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let temp_path = format!("{}.tmp", self.path);
+        let mut temp = BufWriter::new(File::create(&temp_path)?);
+        temp.write_all(&header())?;
+        for (&hash, moves) in &self.positions {
+            temp.write_all(&encode(hash, moves))?;
+        }
+        temp.flush()?;
+        drop(temp);
+        fs::rename(&temp_path, &self.path)?;
+        self.writer = BufWriter::new(open_append(&self.path)?);
+        self.records = self.positions.len();
+        Ok(())
+    }
+}
+
+fn open_append(path: &str) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn header() -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&KEY_VERSION.to_le_bytes());
+    header
+}
+
+fn encode(hash: u64, moves: &[ExperienceMove]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + moves.len() * MOVE_SIZE);
+    record.extend_from_slice(&hash.to_le_bytes());
+    record.push(moves.len() as u8);
+    for mov in moves {
+        record.extend_from_slice(&mov.mov.0.to_le_bytes());
+        record.extend_from_slice(&mov.visits.to_le_bytes());
+        record.extend_from_slice(&mov.score.unwrap_or_default().to_le_bytes());
+    }
+    record
+}
+
+static EXPERIENCE: Mutex<Option<Experience>> = Mutex::new(None);
+
+fn experience() -> std::sync::MutexGuard<'static, Option<Experience>> {
+    EXPERIENCE.lock().expect("experience lock poisoned")
+}
+
+// reads (or creates) the experience file, an empty path turns it off
+pub fn load_experience(path: &str) -> Result<usize, String> {
+    if path.is_empty() || path == "<empty>" {
+        *experience() = None;
+        return Ok(0);
+    }
+
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(format!("couldn't read experience file {path}: {e}")),
+    };
+    // an empty file is a new one, and anything else has to be ours and use the current keys
+    let mut rest = bytes.as_slice();
+    if !bytes.is_empty() {
+        rest = bytes
+            .strip_prefix(MAGIC.as_slice())
+            .ok_or_else(|| format!("{path} isn't an experience file"))?;
+        let version = rest
+            .get(..4)
+            .map(|version| u32::from_le_bytes(version.try_into().expect("4 bytes")));
+        if version != Some(KEY_VERSION) {
+            return Err(format!(
+                "experience file {path} was written with different position keys, delete it to start over"
+            ));
+        }
+        rest = &rest[4..];
+    }
+
+    let mut positions = HashMap::new();
+    let mut records = 0;
+    // a record cut short by a crash is ignored
+    while rest.len() >= RECORD_HEADER_SIZE {
+        let hash = u64::from_le_bytes(rest[..8].try_into().expect("8 bytes"));
+        let count = rest[8] as usize;
+        let Some(record) = rest.get(RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + count * MOVE_SIZE)
+        else {
+            break;
+        };
+        let moves = record
+            .chunks_exact(MOVE_SIZE)
+            .map(|chunk| ExperienceMove {
+                mov: Move(u16::from_le_bytes([chunk[0], chunk[1]])),
+                visits: u32::from_le_bytes(chunk[2..6].try_into().expect("4 bytes")),
+                score: Some(f32::from_le_bytes(
+                    chunk[6..10].try_into().expect("4 bytes"),
+                )),
+            })
+            .collect();
+        positions.insert(hash, moves);
+        records += 1;
+        rest = &rest[RECORD_HEADER_SIZE + count * MOVE_SIZE..];
+    }
+
+    let mut file =
+        open_append(path).map_err(|e| format!("couldn't open experience file {path}: {e}"))?;
+    if bytes.is_empty() {
+        file.write_all(&header())
+            .map_err(|e| format!("couldn't write to experience file {path}: {e}"))?;
+    }
+    let mut loaded = Experience {
+        path: path.to_owned(),
+        positions,
+        records,
+        writer: BufWriter::new(file),
+    };
+    // also drops a record cut short at the end
+    if loaded.records != loaded.positions.len() || !rest.is_empty() {
+        loaded
+            .compact()
+            .map_err(|e| format!("couldn't rewrite experience file {path}: {e}"))?;
+    }
+    let count = loaded.positions.len();
+    *experience() = Some(loaded);
+    Ok(count)
+}
+
+// what past searches of this position found, if there were any
+pub fn lookup(hash: u64) -> Option<Vec<ExperienceMove>> {
+    experience().as_ref()?.positions.get(&hash).cloned()
+}
+
+// replaces what's known about the position with a finished search and writes it to the file,
+// that search was seeded with the old record so nothing is really thrown away
+pub fn record(hash: u64, moves: &[ExperienceMove]) -> io::Result<()> {
+    let mut guard = experience();
+    let Some(experience) = guard.as_mut() else {
+        return Ok(());
+    };
+
+    let known: Vec<ExperienceMove> = moves
+        .iter()
+        .filter(|mov| mov.visits != 0 && mov.score.is_some())
+        .copied()
+        .collect();
+    if known.is_empty() {
+        return Ok(());
+    }
+
+    experience.writer.write_all(&encode(hash, &known))?;
+    experience.writer.flush()?;
+    experience.positions.insert(hash, known);
+    experience.records += 1;
+    if experience.records > 2 * experience.positions.len() {
+        experience.compact()?;
+    }
+    Ok(())
+}
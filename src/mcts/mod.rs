@@ -15,8 +15,11 @@
     You should have received a copy of the GNU General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
+pub mod book;
+pub mod experience;
 pub mod node;
 pub mod search;
 pub mod time;
 pub mod tree;
 pub mod tree_half;
+pub mod value_cache;
@@ -17,6 +17,11 @@
 */
 use crate::types::moves::Move;
 use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU8, Ordering};
+
+// tablebase results are proven with this many plies, far more than any real mate, so they never
+// get reported as mates and the search still prefers a real mate over them
+pub const TABLEBASE_PLIES: u16 = 1 << 14;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(u8)]
@@ -39,45 +44,244 @@ impl GameResult {
     pub fn is_terminal(self) -> bool {
         self != Self::Ongoing
     }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Win,
+            1 => Self::Draw,
+            2 => Self::Loss,
+            3 => Self::Ongoing,
+            _ => panic!("invalid game result {value}"),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+// every field is atomic so that the tree can be shared between search threads
+// floats are stored as their bits
+#[derive(Debug)]
 pub struct Node {
-    pub mov: Move,
-    pub first_child: u32,
-    pub child_count: u8,
-    pub visits: u32,
-    pub total_score: f32,
-    pub result: GameResult,
-    pub policy: f32,
-    pub gini_impurity: f32,
+    mov: AtomicU16,
+    first_child: AtomicU32,
+    child_count: AtomicU8,
+    visits: AtomicU32,
+    total_score: AtomicU32,
+    // threads currently below this node, used as virtual loss
+    threads: AtomicU16,
+    result: AtomicU8,
+    // for proven wins and losses, how many plies until mate
+    proven_plies: AtomicU16,
+    policy: AtomicU32,
+    gini_impurity: AtomicU32,
+    // held while expanding or copying children
+    lock: AtomicBool,
 }
 impl Node {
     pub fn new(mov: Move, policy: f32) -> Self {
         Self {
-            mov,
-            first_child: 0,
-            child_count: 0,
-            visits: 0,
-            total_score: 0.0,
-            result: GameResult::Ongoing,
-            policy,
-            gini_impurity: 0.0,
+            mov: AtomicU16::new(mov.0),
+            first_child: AtomicU32::new(0),
+            child_count: AtomicU8::new(0),
+            visits: AtomicU32::new(0),
+            total_score: AtomicU32::new(0.0f32.to_bits()),
+            threads: AtomicU16::new(0),
+            result: AtomicU8::new(GameResult::Ongoing as u8),
+            proven_plies: AtomicU16::new(0),
+            policy: AtomicU32::new(policy.to_bits()),
+            gini_impurity: AtomicU32::new(0.0f32.to_bits()),
+            lock: AtomicBool::new(false),
+        }
+    }
+
+    // reinitialise this slot as a fresh node
+    pub fn set(&self, mov: Move, policy: f32) {
+        self.mov.store(mov.0, Ordering::Relaxed);
+        self.first_child.store(0, Ordering::Relaxed);
+        self.child_count.store(0, Ordering::Relaxed);
+        self.visits.store(0, Ordering::Relaxed);
+        self.total_score.store(0.0f32.to_bits(), Ordering::Relaxed);
+        self.threads.store(0, Ordering::Relaxed);
+        self.result.store(GameResult::Ongoing as u8, Ordering::Relaxed);
+        self.proven_plies.store(0, Ordering::Relaxed);
+        self.policy.store(policy.to_bits(), Ordering::Relaxed);
+        self.gini_impurity.store(0.0f32.to_bits(), Ordering::Relaxed);
+        self.lock.store(false, Ordering::Relaxed);
+    }
+
+    pub fn copy_from(&self, other: &Self) {
+        self.mov.store(other.mov.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.first_child
+            .store(other.first_child.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.child_count
+            .store(other.child_count.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.visits
+            .store(other.visits.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.total_score
+            .store(other.total_score.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.threads.store(0, Ordering::Relaxed);
+        self.result
+            .store(other.result.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.proven_plies
+            .store(other.proven_plies.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.policy
+            .store(other.policy.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.gini_impurity
+            .store(other.gini_impurity.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.lock.store(false, Ordering::Relaxed);
+    }
+
+    pub fn mov(&self) -> Move {
+        Move(self.mov.load(Ordering::Relaxed))
+    }
+
+    pub fn first_child(&self) -> u32 {
+        self.first_child.load(Ordering::Acquire)
+    }
+
+    pub fn child_count(&self) -> u8 {
+        self.child_count.load(Ordering::Acquire)
+    }
+
+    // first_child has to be valid before child_count becomes non zero
+    pub fn set_children(&self, first_child: u32, child_count: u8) {
+        self.first_child.store(first_child, Ordering::Release);
+        self.child_count.store(child_count, Ordering::Release);
+    }
+
+    pub fn set_first_child(&self, first_child: u32) {
+        self.first_child.store(first_child, Ordering::Release);
+    }
+
+    pub fn visits(&self) -> u32 {
+        self.visits.load(Ordering::Relaxed)
+    }
+
+    pub fn total_score(&self) -> f32 {
+        f32::from_bits(self.total_score.load(Ordering::Relaxed))
+    }
+
+    pub fn threads(&self) -> u16 {
+        self.threads.load(Ordering::Relaxed)
+    }
+
+    pub fn result(&self) -> GameResult {
+        GameResult::from_u8(self.result.load(Ordering::Acquire))
+    }
+
+    pub fn set_result(&self, result: GameResult) {
+        self.set_proven(result, 0);
+    }
+
+    pub fn proven_plies(&self) -> u16 {
+        self.proven_plies.load(Ordering::Relaxed)
+    }
+
+    // plies has to be visible by the time anyone sees the result
+    pub fn set_proven(&self, result: GameResult, plies: u16) {
+        self.proven_plies.store(plies, Ordering::Relaxed);
+        self.result.store(result as u8, Ordering::Release);
+    }
+
+    // signed mate in moves for the side to move here, if this node is proven
+    pub fn mate(&self) -> Option<i32> {
+        if self.proven_plies() >= TABLEBASE_PLIES {
+            return None;
+        }
+        let moves = (self.proven_plies() as i32 + 1) / 2;
+        match self.result() {
+            GameResult::Win => Some(moves),
+            GameResult::Loss => Some(-moves),
+            _ => None,
+        }
+    }
+
+    // same thing but for the side that played the move into this node
+    pub fn parent_mate(&self) -> Option<i32> {
+        if self.proven_plies() >= TABLEBASE_PLIES {
+            return None;
+        }
+        let moves = (self.proven_plies() as i32 + 2) / 2;
+        match self.result() {
+            GameResult::Win => Some(-moves),
+            GameResult::Loss => Some(moves),
+            _ => None,
         }
     }
 
+    pub fn policy(&self) -> f32 {
+        f32::from_bits(self.policy.load(Ordering::Relaxed))
+    }
+
+    pub fn gini_impurity(&self) -> f32 {
+        f32::from_bits(self.gini_impurity.load(Ordering::Relaxed))
+    }
+
+    pub fn set_gini_impurity(&self, gini_impurity: f32) {
+        self.gini_impurity
+            .store(gini_impurity.to_bits(), Ordering::Relaxed);
+    }
+
     pub fn average_score(&self) -> f32 {
-        self.total_score / self.visits as f32
+        self.total_score() / self.visits() as f32
+    }
+
+    pub fn update(&self, score: f32) {
+        self.visits.fetch_add(1, Ordering::Relaxed);
+        let _ = self
+            .total_score
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f32::from_bits(bits) + score).to_bits())
+            });
+    }
+
+    // a batch of visits at once, for statistics that didn't come from this tree
+    pub fn add_visits(&self, visits: u32, total_score: f32) {
+        self.visits.fetch_add(visits, Ordering::Relaxed);
+        let _ = self
+            .total_score
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f32::from_bits(bits) + total_score).to_bits())
+            });
+    }
+
+    pub fn add_thread(&self) {
+        self.threads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn remove_thread(&self) {
+        self.threads.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn try_lock(&self) -> bool {
+        self.lock
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    pub fn lock(&self) {
+        while !self.try_lock() {
+            std::hint::spin_loop();
+        }
+    }
+
+    pub fn unlock(&self) {
+        self.lock.store(false, Ordering::Release);
+    }
+
+    pub fn wait_unlocked(&self) {
+        while self.lock.load(Ordering::Acquire) {
+            std::hint::spin_loop();
+        }
     }
 
     pub fn children_range(&self) -> Range<usize> {
-        let start = self.first_child as usize;
-        let end = start + self.child_count as usize;
+        let child_count = self.child_count() as usize;
+        let start = self.first_child() as usize;
+        let end = start + child_count;
         start..end
     }
 
-    pub fn dereference(&mut self) {
-        self.first_child = 0;
-        self.child_count = 0;
+    pub fn dereference(&self) {
+        self.first_child.store(0, Ordering::Relaxed);
+        self.child_count.store(0, Ordering::Relaxed);
     }
 }
@@ -19,21 +19,60 @@
 use crate::datagen::NODE_LIMIT;
 use crate::{
     board::{Board, Position},
-    mcts::time::Limiters,
+    mcts::time::{Limiters, SoftTime},
     nets::policy::PolicyAccumulator,
+    syzygy::tablebases,
     tunable::Tunables,
-    types::{moves::Move, MoveList},
+    types::{moves::Move, MoveList, PolicyList},
     uci::UciOptions,
 };
-use std::time::Instant;
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::Instant,
+};
 use crate::nets::value::ValueNetworkState;
 use super::{
-    node::{GameResult, Node},
-    tree::{SearchTree, IND_MASK},
+    book,
+    experience::{self, ExperienceMove},
+    node::{GameResult, Node, TABLEBASE_PLIES},
+    tree::SearchTree,
+    value_cache::ValueCache,
 };
 
 const MATE_SCORE: i32 = 32000;
 pub const EVAL_SCALE: u16 = 400;
+// how hard lcb punishes few visits
+const LCB_MULT: f32 = 0.6;
+// past searches from the experience file count as at most this many root visits
+const EXPERIENCE_VISITS: f32 = 256.0;
+
+// how the move to play (and the pv) is picked from the visited children
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MoveSelection {
+    Visits,
+    Lcb,
+}
+
+impl MoveSelection {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "visits" => Some(Self::Visits),
+            "lcb" => Some(Self::Lcb),
+            _ => None,
+        }
+    }
+}
+
+// by hash, which leaves out the 50 move counter, so a root sent as a fen with a different
+// counter still finds its subtree
+fn same_position(a: &Position, b: &Position) -> bool {
+    a.hash() == b.hash()
+}
 
 pub fn to_cp(score: f32) -> i32 {
     if score == 1.0 {
@@ -45,40 +84,50 @@ pub fn to_cp(score: f32) -> i32 {
     }
 }
 
-pub struct Engine {
-    tree: SearchTree,
+// everything a single search thread needs, the tree is shared between all of them
+struct SearchThread<'a> {
+    tree: &'a SearchTree,
+    value_cache: &'a ValueCache,
+    // root moves we're allowed to search, empty means all of them
+    search_moves: &'a [Move],
+    // what past searches of the root found
+    experience: &'a [ExperienceMove],
     board: Board,
     depth: u32,
-    pub nodes: u128,
-    start: Instant,
     policy: PolicyAccumulator,
     value: ValueNetworkState,
 }
 
-impl Engine {
-    #[must_use]
-    pub fn new() -> Self {
+impl<'a> SearchThread<'a> {
+    fn new(
+        tree: &'a SearchTree,
+        value_cache: &'a ValueCache,
+        search_moves: &'a [Move],
+        experience: &'a [ExperienceMove],
+    ) -> Self {
         Self {
-            tree: SearchTree::default(),
+            tree,
+            value_cache,
+            search_moves,
+            experience,
             board: Board::default(),
             depth: 0,
-            nodes: 0,
-            start: Instant::now(),
             policy: PolicyAccumulator::default(),
             value: ValueNetworkState::default(),
         }
     }
-    fn select(&mut self, current: usize, tunables: &Tunables, root: bool) -> usize {
-        let node = self.tree[current];
+
+    fn select(&self, current: usize, tunables: &Tunables, root: bool) -> usize {
+        let node = &self.tree[current];
 
         #[cfg(feature = "datagen")]
-        let e_scale = (node.visits as f32).sqrt();
+        let e_scale = (node.visits() as f32).sqrt();
 
         #[cfg(not(feature = "datagen"))]
         let e_scale = {
-            let mut scale = (node.visits as f32).sqrt();
+            let mut scale = (node.visits() as f32).sqrt();
             scale *= (tunables.gini_base()
-                - tunables.gini_log_mult() * (node.gini_impurity + 0.001).ln())
+                - tunables.gini_log_mult() * (node.gini_impurity() + 0.001).ln())
             .min(tunables.gini_min());
             scale
         };
@@ -89,7 +138,7 @@ impl Engine {
             tunables.default_cpuct()
         };
         let vis_scale = tunables.cpuct_visits_scale() * 128.0;
-        cpuct *= 1.0 + ((node.visits as f32 + vis_scale) / vis_scale).ln();
+        cpuct *= 1.0 + ((node.visits() as f32 + vis_scale) / vis_scale).ln();
 
         let e = cpuct * e_scale;
 
@@ -98,14 +147,18 @@ impl Engine {
         let mut best_child = 0;
         let mut best_child_uct = f32::NEG_INFINITY;
         for child_idx in node.children_range() {
-            let child = self.tree[child_idx];
-            let average_score = if child.visits == 0 {
-                1.0 - parent_q
-            } else {
-                child.average_score()
+            let child = &self.tree[child_idx];
+            // other threads currently searching this child count as losses for it
+            let visits = child.visits() + child.threads() as u32;
+            let average_score = match child.result() {
+                // proven, no need to guess
+                GameResult::Win => 0.0,
+                GameResult::Loss => 1.0,
+                _ if visits == 0 => 1.0 - parent_q,
+                _ => child.total_score() / visits as f32,
             };
-            let p = child.policy;
-            let uct = average_score + e * p / (1 + child.visits) as f32;
+            let p = child.policy();
+            let uct = average_score + e * p / (1 + visits) as f32;
 
             if uct > best_child_uct {
                 best_child = child_idx;
@@ -117,12 +170,28 @@ impl Engine {
     }
 
     fn expand(&mut self, node_idx: usize, root: bool, tunables: &Tunables) -> Option<()> {
-        let next = self.tree.next() as u32;
-        let half_size = self.tree.half_size;
-        let node = &mut self.tree[node_idx];
+        let node = &self.tree[node_idx];
+
+        // only one thread expands a node, the rest wait for it to be done
+        if !node.try_lock() {
+            node.wait_unlocked();
+            return Some(());
+        }
+        if node.child_count() != 0 || node.result().is_terminal() {
+            node.unlock();
+            return Some(());
+        }
+
+        let result = self.expand_locked(node_idx, root, tunables);
+        node.unlock();
+        result
+    }
+
+    fn expand_locked(&mut self, node_idx: usize, root: bool, tunables: &Tunables) -> Option<()> {
+        let node = &self.tree[node_idx];
 
         if self.board.is_drawn() {
-            node.result = GameResult::Draw;
+            node.set_result(GameResult::Draw);
             return Some(());
         }
 
@@ -131,209 +200,594 @@ impl Engine {
 
         // checkmate or stalemate
         if moves.is_empty() {
-            node.result = if self.board.in_check() {
+            node.set_result(if self.board.in_check() {
                 GameResult::Loss
             } else {
                 GameResult::Draw
-            };
+            });
             return Some(());
         }
 
-        if (next as usize & IND_MASK) + moves.len() >= half_size {
-            return None;
+        // the root gets its moves from dtz instead, so it still has something to play
+        if !root {
+            if let Some(result) = tablebases().and_then(|tb| tb.probe_wdl(&mut self.board)) {
+                node.set_proven(result, TABLEBASE_PLIES);
+                return Some(());
+            }
+        }
+
+        // go searchmoves, policy gets normalized over whatever is left
+        if root && !self.search_moves.is_empty() {
+            moves.retain(|mov| self.search_moves.contains(mov));
         }
 
+        let first_child = self.tree.reserve(moves.len())?;
+
         // get initial policy values
         self.board.policy_load(&mut self.policy);
-        let mut policy: Vec<f32> = vec![0.0; moves.len()];
+        let mut policy = PolicyList::new();
+        self.board.get_policies(&moves, &self.policy, &mut policy);
+        let pst = tunables.default_pst() + tunables.root_pst_bonus() * root as i32 as f32;
         let mut policy_sum: f32 = 0.0;
         let mut sum_of_squares: f32 = 0.0;
-        for i in 0..moves.len() {
-            let unscaled = self.board.get_policy(moves[i], &mut self.policy);
-            policy[i] = (unscaled
-                / (tunables.default_pst() + tunables.root_pst_bonus() * root as i32 as f32))
-                .exp();
-            policy_sum += policy[i];
+        for item in policy.iter_mut() {
+            *item = (*item / pst).exp();
+            policy_sum += *item;
         }
         // normalize
-        for item in policy.iter_mut().take(moves.len()) {
+        for item in policy.iter_mut() {
             *item /= policy_sum;
             sum_of_squares += *item * *item;
         }
 
-        node.first_child = next;
-        node.child_count = moves.len() as u8;
-        node.gini_impurity = (1.0 - sum_of_squares).clamp(0.0, 1.0);
+        // past searches of the root pull the priors towards what they ended up visiting
+        let known = |mov: Move| self.experience.iter().find(|known| known.mov == mov);
+        let known_visits: u32 = if root {
+            moves
+                .iter()
+                .filter_map(|&mov| known(mov))
+                .map(|known| known.visits)
+                .sum()
+        } else {
+            0
+        };
+        if known_visits != 0 {
+            let weight = known_visits as f32 / (known_visits as f32 + EXPERIENCE_VISITS);
+            for (item, &mov) in policy.iter_mut().zip(moves.iter()) {
+                let share =
+                    known(mov).map_or(0.0, |known| known.visits as f32 / known_visits as f32);
+                *item = (1.0 - weight) * *item + weight * share;
+            }
+        }
 
         for i in 0..moves.len() {
-            let node2 = Node::new(moves[i], policy[i]);
-            self.tree.push(node2)?;
+            self.tree[first_child + i].set(moves[i], policy[i]);
         }
 
+        // and their scores carry over, scaled down so the search can still change its mind
+        if known_visits != 0 {
+            let scale = (EXPERIENCE_VISITS / known_visits as f32).min(1.0);
+            for i in 0..moves.len() {
+                // book moves only say how often they're played, not how they score
+                if let Some(&ExperienceMove {
+                    visits,
+                    score: Some(score),
+                    ..
+                }) = known(moves[i])
+                {
+                    let visits = (visits as f32 * scale).round() as u32;
+                    self.tree[first_child + i].add_visits(visits, score * visits as f32);
+                    node.add_visits(visits, (1.0 - score) * visits as f32);
+                }
+            }
+        }
+
+        node.set_gini_impurity((1.0 - sum_of_squares).clamp(0.0, 1.0));
+        node.set_children(first_child as u32, moves.len() as u8);
+
         Some(())
     }
 
     // not an actual simulation, but for nomenclature consistent with normal mcts, i decided to call it that.
     fn simulate(&mut self, node_idx: usize) -> f32 {
-        let node = self.tree[node_idx];
-        node.result.score().unwrap_or_else(|| {
-            1.0 / (1.0 + (-self.board.evaluate(&mut self.value) as f32 / EVAL_SCALE as f32).exp())
+        let node = &self.tree[node_idx];
+        node.result().score().unwrap_or_else(|| {
+            let hash = self.board.current_state().hash();
+            let eval = self.value_cache.get(hash).unwrap_or_else(|| {
+                let eval = self.board.evaluate(&mut self.value);
+                self.value_cache.insert(hash, eval);
+                eval
+            });
+            1.0 / (1.0 + (-eval as f32 / EVAL_SCALE as f32).exp())
         })
     }
 
     fn mcts(&mut self, current_node: usize, root: bool, tunables: &Tunables) -> Option<f32> {
-        let current_node_ref = &self.tree[current_node];
+        let tree = self.tree;
+        let current_node_ref = &tree[current_node];
 
-        let mut score = if current_node_ref.result.is_terminal() || current_node_ref.visits == 0 {
-            self.simulate(current_node)
-        } else {
-            if current_node_ref.child_count == 0 {
-                self.expand(current_node, root, tunables)?;
-                if self.tree[current_node].result.is_terminal() {
-                    return Some(self.simulate(current_node));
+        current_node_ref.add_thread();
+        let score = self.descend(current_node, root, tunables).map(|score| 1.0 - score);
+        if let Some(score) = score {
+            current_node_ref.update(score);
+        }
+        current_node_ref.remove_thread();
+
+        score
+    }
+
+    fn descend(&mut self, current_node: usize, root: bool, tunables: &Tunables) -> Option<f32> {
+        let tree = self.tree;
+        let current_node_ref = &tree[current_node];
+
+        if current_node_ref.result().is_terminal() || current_node_ref.visits() == 0 {
+            return Some(self.simulate(current_node));
+        }
+
+        if current_node_ref.child_count() == 0 {
+            self.expand(current_node, root, tunables)?;
+            if current_node_ref.result().is_terminal() {
+                return Some(self.simulate(current_node));
+            }
+            // another thread tried to expand it and ran out of space
+            if current_node_ref.child_count() == 0 {
+                return None;
+            }
+        }
+
+        tree.copy_children(current_node)?;
+
+        let next_index = self.select(current_node, tunables, root);
+
+        self.board.make_move(tree[next_index].mov());
+        self.depth += 1;
+
+        let score = self.mcts(next_index, false, tunables)?;
+        self.prove(current_node, next_index);
+        Some(score)
+    }
+
+    // mcts-solver, a child that's lost for the opponent wins this node,
+    // and if every child is won for the opponent this node is lost
+    fn prove(&self, node_idx: usize, child_idx: usize) {
+        let node = &self.tree[node_idx];
+        let child = &self.tree[child_idx];
+        match child.result() {
+            GameResult::Loss => {
+                let plies = child.proven_plies() + 1;
+                if node.result() != GameResult::Win || plies < node.proven_plies() {
+                    node.set_proven(GameResult::Win, plies);
                 }
             }
+            GameResult::Win => {
+                let mut longest = 0;
+                for other_idx in node.children_range() {
+                    let other = &self.tree[other_idx];
+                    if other.result() != GameResult::Win {
+                        return;
+                    }
+                    longest = longest.max(other.proven_plies());
+                }
+                node.set_proven(GameResult::Loss, longest + 1);
+            }
+            _ => (),
+        }
+    }
 
-            self.tree.copy_children(current_node)?;
+    // one playout from the root, returns None if the tree ran out of space
+    fn playout(&mut self, root_state: &Position, root_ctm: u8, root_ply: i16, tunables: &Tunables) -> Option<()> {
+        self.board.load_state(root_state, root_ctm);
+        self.board.ply = root_ply;
+        self.depth = 1;
 
-            let next_index = self.select(current_node, tunables, root);
+        self.mcts(self.tree.root_node(), true, tunables)?;
+        Some(())
+    }
+}
 
-            self.board.make_move(self.tree[next_index].mov);
-            self.depth += 1;
+// when the last info line went out, outlives the main thread loop across tree half switches
+struct InfoTimer {
+    prev_avg_depth: u32,
+    last_print: Instant,
+}
 
-            self.mcts(next_index, false, tunables)?
-        };
+impl InfoTimer {
+    fn new() -> Self {
+        Self {
+            prev_avg_depth: 1,
+            last_print: Instant::now(),
+        }
+    }
+}
+
+pub struct Engine {
+    tree: SearchTree,
+    value_cache: ValueCache,
+    board: Board,
+    nodes: AtomicU64,
+    total_depth: AtomicU64,
+    seldepth: AtomicU32,
+    start: Instant,
+    abort: Arc<AtomicBool>,
+    pondering: Arc<AtomicBool>,
+    // the root was expanded with only some of its moves
+    root_restricted: bool,
+    move_selection: MoveSelection,
+}
+
+impl Engine {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            tree: SearchTree::default(),
+            value_cache: ValueCache::default(),
+            board: Board::default(),
+            nodes: AtomicU64::new(0),
+            total_depth: AtomicU64::new(0),
+            seldepth: AtomicU32::new(0),
+            start: Instant::now(),
+            abort: Arc::new(AtomicBool::new(false)),
+            pondering: Arc::new(AtomicBool::new(false)),
+            root_restricted: false,
+            move_selection: MoveSelection::Visits,
+        }
+    }
 
-        score = 1.0 - score;
+    // shared flag that makes a running search stop as soon as it can
+    pub fn abort_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.abort)
+    }
 
-        let current_node_ref_mut = &mut self.tree[current_node];
-        current_node_ref_mut.visits += 1;
-        current_node_ref_mut.total_score += score;
+    // while set, limiters are ignored and the clock doesn't run, cleared on ponderhit
+    pub fn ponder_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.pondering)
+    }
 
-        Some(score)
+    pub fn nodes(&self) -> u64 {
+        self.nodes.load(Ordering::Relaxed)
     }
 
-    fn get_best_move(&mut self, root_node: usize) -> (usize, f32) {
-        let root = self.tree[root_node];
+    fn avg_depth(&self) -> u32 {
+        (self.total_depth.load(Ordering::Relaxed) as f64 / self.nodes() as f64).round() as u32
+    }
 
-        let mut best = None;
-        let mut best_score = f32::NEG_INFINITY;
+    // bookkeeping after each playout, shared by all threads
+    fn record_playout(&self, depth: u32) {
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+        self.total_depth.fetch_add(depth as u64, Ordering::Relaxed);
+        self.seldepth.fetch_max(depth, Ordering::Relaxed);
+    }
 
-        for node_idx in root.children_range() {
-            let node = self.tree[node_idx];
-            let score = node.average_score();
+    // proven results outrank everything else: mate as fast as possible, get mated as slowly
+    // as possible, the rest goes by the move selection option
+    fn rank(&self, child: &Node) -> (i32, f32) {
+        match child.result() {
+            GameResult::Loss => (2, -(child.proven_plies() as f32)),
+            GameResult::Win => (0, child.proven_plies() as f32),
+            _ => match self.move_selection {
+                MoveSelection::Visits => (1, child.visits() as f32),
+                MoveSelection::Lcb => (
+                    1,
+                    child.average_score() - LCB_MULT / (child.visits() as f32).sqrt(),
+                ),
+            },
+        }
+    }
 
-            if score > best_score {
-                best = Some(node_idx);
-                best_score = score;
+    fn best_child(&self, node_idx: usize) -> Option<usize> {
+        let mut best = None;
+        let mut best_rank = (-1, f32::NEG_INFINITY);
+        for child_idx in self.tree[node_idx].children_range() {
+            let child = &self.tree[child_idx];
+            if child.visits() == 0 {
+                continue;
+            }
+            let child_rank = self.rank(child);
+            if child_rank > best_rank {
+                best = Some(child_idx);
+                best_rank = child_rank;
             }
         }
+        best
+    }
+
+    // nothing left to search once the root is proven
+    fn root_solved(&self) -> bool {
+        let root = &self.tree[self.tree.root_node()];
+        root.result().is_terminal() && root.child_count() != 0
+    }
 
-        (best.expect("nothing"), best_score)
+    fn update_soft_time(&self, soft_time: &mut SoftTime, tunables: &Tunables) {
+        let root = self.tree.root_node();
+        let Some(best) = self.best_child(root) else {
+            return;
+        };
+        let best = &self.tree[best];
+        let visit_share = best.visits() as f32 / self.tree[root].visits().max(1) as f32;
+        soft_time.update(best.mov(), best.average_score(), visit_share, tunables);
+    }
+
+    // a search stopped before any root child was visited still has to play something
+    fn get_best_move(&self, root_node: usize, fallback: Move) -> (Move, f32) {
+        match self.best_child(root_node) {
+            Some(best) => (self.tree[best].mov(), self.tree[best].average_score()),
+            None => (fallback, 0.5),
+        }
     }
 
-    pub fn get_pv(&mut self, root_node: usize) -> (Vec<Move>, f32, bool) {
+    pub fn get_pv(&self, root_node: usize) -> (Vec<Move>, f32) {
         let mut pv = vec![];
-        let mut ends_in_mate = false;
         let mut root_score = 0.0;
         let mut node_idx = root_node;
-        loop {
-            let node = self.tree[node_idx];
-            if node.result.is_terminal() || node.child_count == 0 {
-                if node.result == GameResult::Loss || node.result == GameResult::Win {
-                    ends_in_mate = true;
-                }
-                break;
-            }
-            let mut has_valid_child = false;
-            let mut best_child_idx = 0;
-            let mut best_child_score = f32::NEG_INFINITY;
-            for child_idx in node.children_range() {
-                let child = self.tree[child_idx];
-                if child.visits == 0 {
-                    continue;
-                }
-                has_valid_child = true;
-                if child.average_score() > best_child_score {
-                    best_child_idx = child_idx;
-                    best_child_score = child.average_score();
-                }
-            }
-
-            if !has_valid_child {
-                break;
-            }
-            pv.push(self.tree[best_child_idx].mov);
+        while let Some(best_child_idx) = self.best_child(node_idx) {
+            pv.push(self.tree[best_child_idx].mov());
             if node_idx == root_node {
-                root_score = best_child_score;
+                root_score = self.tree[best_child_idx].average_score();
             }
             node_idx = best_child_idx;
         }
 
-        (pv, root_score, ends_in_mate)
+        (pv, root_score)
+    }
+
+    // the expected reply to the move we're about to play, what we'd ponder on
+    pub fn ponder_move(&self) -> Option<Move> {
+        self.get_pv(self.tree.root_node()).0.get(1).copied()
     }
 
-    pub fn find(&mut self, start: usize, state: &Position, depth: u8) -> usize {
-        let start_node = self.tree[start];
+    // the node for the board's position at most `depth` plies below the old root, following the
+    // game's own moves if the old root is in its history and searching the tree by hash if not
+    pub fn find(&mut self, board: &Board, depth: u8) -> Option<usize> {
+        let root = self.tree.root_node();
+        let old_root = *self.board.current_state();
+        let recent = &board.states[board.states.len().saturating_sub(depth as usize + 1)..];
+        if let Some(ply) = recent
+            .iter()
+            .rposition(|state| same_position(state, &old_root))
+        {
+            if let Some(found) = self.follow(root, &recent[ply + 1..]) {
+                return Some(found);
+            }
+        }
+        self.find_below(root, board.current_state(), depth)
+    }
 
-        if self.board.current_state() == state {
-            return start;
+    // walks down the children leading to each position of the path in turn
+    fn follow(&mut self, start: usize, path: &[Position]) -> Option<usize> {
+        let mut node = Some(start);
+        let mut made = 0;
+        for target in path {
+            node = node.and_then(|node| {
+                self.tree[node].children_range().find(|&child_idx| {
+                    self.board.make_move(self.tree[child_idx].mov());
+                    let found = same_position(self.board.current_state(), target);
+                    self.board.undo_move();
+                    found
+                })
+            });
+            let Some(node) = node else {
+                break;
+            };
+            self.board.make_move(self.tree[node].mov());
+            made += 1;
         }
-        if start == (1 << 31) - 1 || depth == 0 {
-            return (1 << 31) - 1;
+        for _ in 0..made {
+            self.board.undo_move();
         }
+        node
+    }
 
-        //let start_node = self.tree[start];
-        //dbg!(start_node);
+    // only expanded nodes are worth finding, a leaf would get searched from scratch anyway
+    fn find_below(&mut self, start: usize, state: &Position, depth: u8) -> Option<usize> {
+        if same_position(self.board.current_state(), state) {
+            return Some(start);
+        }
+        if depth == 0 {
+            return None;
+        }
 
-        for i in start_node.children_range() {
-            let i_node = self.tree[i];
-            self.board.make_move(i_node.mov);
-            let found = self.find(i, state, depth - 1);
+        for child_idx in self.tree[start].children_range() {
+            if self.tree[child_idx].child_count() == 0 {
+                continue;
+            }
+            self.board.make_move(self.tree[child_idx].mov());
+            let found = self.find_below(child_idx, state, depth - 1);
             self.board.undo_move();
 
-            if found != (1 << 31) - 1 {
+            if found.is_some() {
                 return found;
             }
         }
 
-        (1 << 31) - 1
+        None
     }
 
-    // todo: SMP (after first release)
     pub fn search(
         &mut self,
         board: Board,
         limiters: Limiters,
+        search_moves: &[Move],
         info: bool,
         options: &UciOptions,
         tunables: &Tunables,
     ) -> Move {
-        self.nodes = 0;
-        let mut seldepth = 0;
-        let mut total_depth: usize = 0;
-        let mut prev_avg_depth = 1;
-        let mut avg_depth;
+        self.nodes.store(0, Ordering::Relaxed);
+        self.total_depth.store(0, Ordering::Relaxed);
+        self.seldepth.store(0, Ordering::Relaxed);
         self.start = Instant::now();
-        let mut last_print = Instant::now();
 
         let root_state = board.states.last().expect("bruh you gave an empty board");
         let root_ctm = board.ctm;
         let root_ply = board.ply;
 
+        // in the tablebases only the moves that keep the best outcome get searched,
+        // unless none of them are in searchmoves
+        let tablebase_moves = tablebases()
+            .and_then(|tb| tb.root_moves(&board))
+            .map(|moves| {
+                let allowed: MoveList = moves
+                    .into_iter()
+                    .filter(|mov| search_moves.is_empty() || search_moves.contains(mov))
+                    .collect();
+                if allowed.is_empty() {
+                    search_moves.iter().copied().collect()
+                } else {
+                    allowed
+                }
+            });
+        let search_moves = tablebase_moves.as_deref().unwrap_or(search_moves);
+
+        // checkmate or stalemate, there's nothing to search
+        let mut root_moves = MoveList::new();
+        board.get_moves(&mut root_moves);
+        let Some(&first_move) = search_moves.first().or(root_moves.first()) else {
+            if info {
+                let score = if board.in_check() { "mate 0" } else { "cp 0" };
+                println!("info depth 0 score {score}");
+            }
+            return Move::NULL_MOVE;
+        };
+
         // attempt to reuse tree
         if self.tree.is_empty() {
-            self.tree.push(Node::new(Move::NULL_MOVE, 0.0));
+            self.tree.push(Move::NULL_MOVE, 0.0);
         } else {
             let root = self.tree.root_node();
-            let found = self.find(root, root_state, 2);
-            if found != (1 << 31) - 1 && self.tree[found].child_count != 0 {
-                self.tree[root] = self.tree[found];
-            } else {
+            let found = self.find(&board, options.reuse_depth);
+            // a root expanded for searchmoves is missing children
+            let restricted =
+                !search_moves.is_empty() || (found == Some(root) && self.root_restricted);
+            let reused = match found {
+                Some(found) if self.tree[found].child_count() != 0 && !restricted => {
+                    self.tree.promote(found).is_some()
+                }
+                _ => false,
+            };
+            if !reused {
                 self.tree.reset();
-                self.tree.push(Node::new(Move::NULL_MOVE, 0.0));
+                self.tree.push(Move::NULL_MOVE, 0.0);
             }
         };
+        self.root_restricted = !search_moves.is_empty();
+        self.move_selection = options.move_selection;
+        let experience = experience::lookup(root_state.hash())
+            .or_else(|| book::lookup(&board))
+            .unwrap_or_default();
+
+        let mut limit_start = self.start;
+        let mut soft_time = SoftTime::new();
+        let mut info_timer = InfoTimer::new();
+        loop {
+            let tree_full = AtomicBool::new(false);
+            let done = AtomicBool::new(false);
+
+            // threads can't switch halves while others are searching, so every thread
+            // stops when the current half fills up and the search resumes after the switch
+            let stop = thread::scope(|s| {
+                for _ in 1..options.thread_count {
+                    s.spawn(|| {
+                        let mut thread = SearchThread::new(
+                            &self.tree,
+                            &self.value_cache,
+                            search_moves,
+                            &experience,
+                        );
+                        while !done.load(Ordering::Relaxed) {
+                            if thread.playout(root_state, root_ctm, root_ply, tunables).is_none() {
+                                tree_full.store(true, Ordering::Relaxed);
+                                break;
+                            }
+                            self.record_playout(thread.depth);
+                        }
+                    });
+                }
+
+                let stop = self.main_thread_loop(
+                    SearchThread::new(&self.tree, &self.value_cache, search_moves, &experience),
+                    (root_state, root_ctm, root_ply),
+                    &limiters,
+                    &mut limit_start,
+                    &mut soft_time,
+                    &mut info_timer,
+                    &tree_full,
+                    info,
+                    options,
+                    tunables,
+                );
+                done.store(true, Ordering::Relaxed);
+                stop
+            });
+
+            if tree_full.load(Ordering::Relaxed) {
+                self.tree.switch_halves();
+            }
+            if stop {
+                break;
+            }
+        }
+
+        if !limiters.use_depth {
+            let duration = self.start.elapsed().as_millis();
+            let avg_depth = self.avg_depth().saturating_sub(1);
+            if info {
+                self.print_info(
+                    self.tree.root_node(),
+                    avg_depth,
+                    self.seldepth.load(Ordering::Relaxed),
+                    duration,
+                    true,
+                    options,
+                );
+            }
+        }
+
+        let (best_move, _best_score) = self.get_best_move(self.tree.root_node(), first_move);
+
+        // a root that was missing moves would leave them out of the experience
+        if !self.root_restricted {
+            let root_moves: Vec<ExperienceMove> = self.tree[self.tree.root_node()]
+                .children_range()
+                .map(|child_idx| {
+                    let child = &self.tree[child_idx];
+                    ExperienceMove {
+                        mov: child.mov(),
+                        visits: child.visits(),
+                        score: Some(child.average_score()),
+                    }
+                })
+                .collect();
+            if let Err(e) = experience::record(root_state.hash(), &root_moves) {
+                println!("info string couldn't write to the experience file: {e}");
+            }
+        }
+
+        self.board.load_state(root_state, root_ctm);
+
+        #[cfg(feature = "datagen")]
+        {
+            self.tree.reset();
+        }
+
+        best_move
+    }
+
+    // searches on the calling thread until a limiter is hit (returns true),
+    // or until the current tree half is full (returns false)
+    #[allow(clippy::too_many_arguments)]
+    fn main_thread_loop(
+        &self,
+        mut thread: SearchThread,
+        (root_state, root_ctm, root_ply): (&Position, u8, i16),
+        limiters: &Limiters,
+        limit_start: &mut Instant,
+        soft_time: &mut SoftTime,
+        info_timer: &mut InfoTimer,
+        tree_full: &AtomicBool,
+        info: bool,
+        options: &UciOptions,
+        tunables: &Tunables,
+    ) -> bool {
+        let mut avg_depth;
 
         #[cfg(feature = "datagen")]
         let mut prev_visit_distribution = vec![];
@@ -341,98 +795,90 @@ impl Engine {
         let mut curr_visit_distribution;
 
         loop {
-            self.board.load_state(root_state, root_ctm);
-            self.board.ply = root_ply;
-            self.depth = 1;
-
-            let result = self.mcts(self.tree.root_node(), true, tunables);
-
-            self.nodes += 1;
-            total_depth += self.depth as usize;
-
-            if self.depth > seldepth {
-                seldepth = self.depth;
+            // a playout that ran out of space didn't search anything
+            if thread
+                .playout(root_state, root_ctm, root_ply, tunables)
+                .is_some()
+            {
+                self.record_playout(thread.depth);
+            } else {
+                tree_full.store(true, Ordering::Relaxed);
             }
 
             // info
-            avg_depth = (total_depth as f64 / self.nodes as f64).round() as u32;
-            if avg_depth > prev_avg_depth || last_print.elapsed().as_secs_f32() > 3.0 {
+            avg_depth = self.avg_depth();
+            if avg_depth > info_timer.prev_avg_depth
+                || info_timer.last_print.elapsed().as_secs_f32() > 3.0
+            {
                 let duration = self.start.elapsed().as_millis();
                 if info && !options.minimal {
                     self.print_info(
                         self.tree.root_node(),
-                        avg_depth - 1,
-                        seldepth,
+                        avg_depth.saturating_sub(1),
+                        self.seldepth.load(Ordering::Relaxed),
                         duration,
                         false,
                         options,
                     );
                 }
-                prev_avg_depth = avg_depth;
-                last_print = Instant::now();
-            }
-
-            if result.is_none() {
-                self.tree.switch_halves();
+                info_timer.prev_avg_depth = avg_depth;
+                info_timer.last_print = Instant::now();
             }
 
             #[cfg(feature = "datagen")]
             {
                 curr_visit_distribution =
-                    vec![0; self.tree[self.tree.root_node()].child_count as usize];
+                    vec![0; self.tree[self.tree.root_node()].child_count() as usize];
                 for (idx, child) in self.tree[self.tree.root_node()]
                     .children_range()
                     .enumerate()
                 {
-                    curr_visit_distribution[idx] = self.tree[child].visits;
+                    curr_visit_distribution[idx] = self.tree[child].visits();
                 }
             }
 
-            if !limiters.check(
-                self.start.elapsed().as_millis(),
-                self.nodes,
-                avg_depth,
-                tunables,
-                #[cfg(feature = "datagen")]
-                &*curr_visit_distribution,
-                #[cfg(feature = "datagen")]
-                &*prev_visit_distribution,
-            ) {
-                break;
+            if soft_time.update_due(self.nodes()) {
+                self.update_soft_time(soft_time, tunables);
             }
 
-            #[cfg(feature = "datagen")]
-            {
-                prev_visit_distribution = curr_visit_distribution.clone();
-            }
-        }
-        if !limiters.use_depth {
-            let duration = self.start.elapsed().as_millis();
-            avg_depth = (total_depth as f64 / self.nodes as f64).round() as u32 - 1;
-            if info {
-                self.print_info(
-                    self.tree.root_node(),
+            // the clock only starts once pondering is over
+            if self.pondering.load(Ordering::Relaxed) {
+                *limit_start = Instant::now();
+            } else {
+                let limit_hit = !limiters.check(
+                    limit_start.elapsed().as_millis(),
+                    self.nodes() as u128,
                     avg_depth,
-                    seldepth,
-                    duration,
-                    true,
-                    options,
+                    soft_time.scale(),
+                    tunables,
+                    #[cfg(feature = "datagen")]
+                    &*curr_visit_distribution,
+                    #[cfg(feature = "datagen")]
+                    &*prev_visit_distribution,
                 );
+                if limit_hit
+                    || limiters.mate_found(self.tree[self.tree.root_node()].mate())
+                    || (!limiters.is_infinite() && self.root_solved())
+                {
+                    return true;
+                }
             }
-        }
 
-        let (index, _best_score) = self.get_best_move(self.tree.root_node());
-        let best_move = self.tree[index].mov;
+            if self.abort.load(Ordering::Relaxed) {
+                return true;
+            }
 
-        self.board.load_state(root_state, root_ctm);
+            if tree_full.load(Ordering::Relaxed) {
+                return false;
+            }
 
-        #[cfg(feature = "datagen")]
-        {
-            self.tree.reset();
+            #[cfg(feature = "datagen")]
+            {
+                prev_visit_distribution = curr_visit_distribution.clone();
+            }
         }
-
-        best_move
     }
+
     #[cfg(feature = "datagen")]
     pub fn datagen_search(
         &mut self,
@@ -440,71 +886,47 @@ impl Engine {
         tunables: &Tunables,
         limiters: Limiters,
     ) -> (Move, i32, Vec<(Move, u16)>) {
-        self.nodes = 0;
+        self.nodes.store(0, Ordering::Relaxed);
+        self.total_depth.store(0, Ordering::Relaxed);
+        self.seldepth.store(0, Ordering::Relaxed);
         self.start = Instant::now();
-        let mut avg_depth;
-        let mut total_depth: usize = 0;
 
         let root_state = board.states.last().expect("bruh you gave an empty board");
         let root_ctm = board.ctm;
         let root_ply = board.ply;
 
         // attempt to reuse tree
-        self.tree.push(Node::new(Move::NULL_MOVE, 0.0));
-
-        let mut prev_visit_distribution = vec![];
-        let mut curr_visit_distribution;
-
-        loop {
-            self.board.load_state(root_state, root_ctm);
-            self.board.ply = root_ply;
-            self.depth = 1;
-
-            let result = self.mcts(self.tree.root_node(), true, tunables);
-
-            self.nodes += 1;
-            total_depth += self.depth as usize;
-
-
-            // info
-            avg_depth = (total_depth as f64 / self.nodes as f64).round() as u32;
-
-            if result.is_none() {
-                self.tree.switch_halves();
-            }
-
-            curr_visit_distribution =
-                vec![0; self.tree[self.tree.root_node()].child_count as usize];
-            for (idx, child) in self.tree[self.tree.root_node()]
-                .children_range()
-                .enumerate()
-            {
-                curr_visit_distribution[idx] = self.tree[child].visits;
-            }
-
-            if !limiters.check(
-                self.start.elapsed().as_millis(),
-                self.nodes,
-                avg_depth,
-                tunables,
-                &*curr_visit_distribution,
-                &*prev_visit_distribution,
-            ) {
-                break;
-            }
-
-            prev_visit_distribution = curr_visit_distribution.clone();
+        self.tree.push(Move::NULL_MOVE, 0.0);
+
+        let tree_full = AtomicBool::new(false);
+        let mut limit_start = self.start;
+        let mut info_timer = InfoTimer::new();
+        while !self.main_thread_loop(
+            SearchThread::new(&self.tree, &self.value_cache, &[], &[]),
+            (root_state, root_ctm, root_ply),
+            &limiters,
+            &mut limit_start,
+            &mut SoftTime::new(),
+            &mut info_timer,
+            &tree_full,
+            false,
+            &UciOptions::default(),
+            tunables,
+        ) {
+            tree_full.store(false, Ordering::Relaxed);
+            self.tree.switch_halves();
         }
 
-        let (best_node_idx, best_score) = self.get_best_move(self.tree.root_node());
-        let best_move = self.tree[best_node_idx].mov;
+        let mut root_moves = MoveList::new();
+        board.get_moves(&mut root_moves);
+        let (best_move, best_score) = self.get_best_move(self.tree.root_node(), root_moves[0]);
 
         // get visit distribution
-        let root_node = self.tree[self.tree.root_node()];
+        let root_node = &self.tree[self.tree.root_node()];
         let mut visit_points: Vec<(Move, u16)> = vec![];
         for child_idx in root_node.children_range() {
-            let child_node = self.tree[child_idx];
-            visit_points.push((child_node.mov, child_node.visits as u16));
+            let child_node = &self.tree[child_idx];
+            visit_points.push((child_node.mov(), child_node.visits() as u16));
         }
 
         self.board.load_state(root_state, root_ctm);
@@ -513,7 +935,7 @@ impl Engine {
         (best_move, to_cp(best_score), visit_points)
     }
     fn print_info(
-        &mut self,
+        &self,
         root_node: usize,
         depth: u32,
         seldepth: u32,
@@ -521,13 +943,15 @@ impl Engine {
         final_info: bool,
         options: &UciOptions,
     ) {
+        // the search runs off the main thread, so keep the whole output together
+        let _stdout = io::stdout().lock();
         if final_info && options.more_info {
             let mut results = Vec::new();
-            for node_idx in self.tree[0].children_range() {
-                let this_node = self.tree[node_idx];
+            for node_idx in self.tree[root_node].children_range() {
+                let this_node = &self.tree[node_idx];
                 let score = this_node.average_score();
                 let pv = self.get_pv(node_idx).0;
-                results.push((this_node.mov, this_node.visits, score, pv));
+                results.push((this_node.mov(), this_node.visits(), score, pv));
             }
             results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
             for (mov, visits, score, pv) in results {
@@ -543,37 +967,60 @@ impl Engine {
                 println!();
             }
         }
-        let (pv, score, ends_in_mate) = self.get_pv(root_node);
-        let nps = if duration == 0 {
-            0
-        } else {
-            self.nodes * 1000 / duration
+        let nodes = self.nodes();
+        let nps = (nodes as u128 * 1000).checked_div(duration).unwrap_or(0);
+        let hashfull = self.tree.hashfull();
+        // depth, time and nps are for the whole search, nodes are for the line
+        let stats = |nodes: u64| {
+            format!(
+                "depth {depth} seldepth {seldepth} nodes {nodes} time {duration} nps {nps} hashfull {hashfull}"
+            )
         };
-        print!(
-            "info depth {} seldepth {} nodes {} time {} nps {} hashfull {} ",
-            depth,
-            seldepth,
-            self.nodes,
-            duration,
-            nps,
-            self.tree.hashfull()
-        );
-        if ends_in_mate {
-            print!("score mate {} ", pv.len() / 2);
+        if options.multi_pv <= 1 {
+            let (pv, score) = self.get_pv(root_node);
+            print_info_line(None, &stats(nodes), score, self.tree[root_node].mate(), &pv);
         } else {
-            print!("score cp {} ", to_cp(score));
-        }
-        print!("pv");
-        for mov in &pv {
-            print!(" {mov}");
+            for (i, child_idx) in self
+                .ranked_root_children(root_node)
+                .into_iter()
+                .take(options.multi_pv)
+                .enumerate()
+            {
+                let child = &self.tree[child_idx];
+                let (mut pv, _) = self.get_pv(child_idx);
+                pv.insert(0, child.mov());
+                print_info_line(
+                    Some(i + 1),
+                    &stats(u64::from(child.visits())),
+                    child.average_score(),
+                    child.parent_mate(),
+                    &pv,
+                );
+            }
         }
-        println!();
+    }
+
+    // visited root children, best first by the same rule as the played move
+    fn ranked_root_children(&self, root_node: usize) -> Vec<usize> {
+        let mut children: Vec<usize> = self.tree[root_node]
+            .children_range()
+            .filter(|&child_idx| self.tree[child_idx].visits() != 0)
+            .collect();
+        children.sort_by(|&a, &b| {
+            self.rank(&self.tree[b])
+                .partial_cmp(&self.rank(&self.tree[a]))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        children
     }
     pub fn resize(&mut self, new_size: usize) {
         self.tree.resize(new_size);
+        self.value_cache = ValueCache::new(new_size);
     }
     pub fn new_game(&mut self) {
         self.tree.reset();
+        // could be for a new net
+        self.value_cache.clear();
     }
 }
 
@@ -582,3 +1029,27 @@ impl Default for Engine {
         Self::new()
     }
 }
+
+fn print_info_line(
+    multipv: Option<usize>,
+    stats: &str,
+    score: f32,
+    mate: Option<i32>,
+    pv: &[Move],
+) {
+    print!("info ");
+    if let Some(multipv) = multipv {
+        print!("multipv {multipv} ");
+    }
+    print!("{stats} ");
+    if let Some(mate) = mate {
+        print!("score mate {mate} ");
+    } else {
+        print!("score cp {} ", to_cp(score));
+    }
+    print!("pv");
+    for mov in pv {
+        print!(" {mov}");
+    }
+    println!();
+}
@@ -1,4 +1,4 @@
-use crate::tunable::Tunables;
+use crate::{tunable::Tunables, types::moves::Move};
 
 /*
     Anura
@@ -22,12 +22,15 @@ pub struct Limiters {
     use_time: bool,
     time: u128,
     increment: u128,
+    moves_to_go: u32,
     use_nodes: bool,
     node_lim: u128,
     pub use_depth: bool,
     depth_limit: u32,
     use_move_time: bool,
     move_time: u128,
+    use_mate: bool,
+    mate: u32,
     #[cfg(feature = "datagen")]
     min_kld: f64,
     #[cfg(feature = "datagen")]
@@ -64,11 +67,58 @@ impl Limiters {
         }
     }
 
+    pub fn set_mate(&mut self, mate: u32) {
+        self.use_mate = mate != 0;
+        self.mate = mate;
+    }
+
+    // 0 means sudden death (or not given)
+    pub fn set_moves_to_go(&mut self, moves_to_go: u32) {
+        self.moves_to_go = moves_to_go;
+    }
+
+    // go mate, done once we've proven a mate at least this short
+    pub fn mate_found(&self, mate: Option<i32>) -> bool {
+        self.use_mate && matches!(mate, Some(moves) if moves > 0 && moves as u32 <= self.mate)
+    }
+
+    // no limits at all (go infinite, go mate), only stop can end it
+    pub fn is_infinite(&self) -> bool {
+        #[cfg(feature = "datagen")]
+        if self.use_kld {
+            return false;
+        }
+        !(self.use_time || self.use_nodes || self.use_depth || self.use_move_time)
+    }
+
+    // never spend more than this on one move, even if the soft limit would allow it
+    fn hard_time_limit(&self, tunables: &Tunables) -> u128 {
+        let base = self.time as f32 / self.time_divisor(tunables)
+            + self.increment as f32 / tunables.inc_divisor();
+        let mut cap = self.time as f32 * tunables.max_time_fraction();
+        // the other moves before the time control need their share too
+        if self.moves_to_go != 0 {
+            cap = cap.min(self.time as f32 / self.moves_to_go as f32 * tunables.mtg_hard_mult());
+        }
+        ((base * tunables.hard_time_mult()) as u128).min(cap as u128)
+    }
+
+    // where we normally stop, scaled by SoftTime
     // maybe gainer idea, give slightly less for the min part
-    fn time_allocated(&self, tunables: &Tunables) -> u128 {
-        ((self.time as f32 / tunables.time_divisor()) as u128
+    fn soft_time_limit(&self, tunables: &Tunables) -> u128 {
+        ((self.time as f32 / self.time_divisor(tunables)) as u128
             + (self.increment as f32 / tunables.inc_divisor()) as u128)
-            .min(self.time)
+            .min(self.hard_time_limit(tunables))
+    }
+
+    // with a known number of moves left in the period spread the time over those,
+    // the last few moves before a time control still keep some in reserve via the hard limit
+    fn time_divisor(&self, tunables: &Tunables) -> f32 {
+        if self.moves_to_go == 0 {
+            tunables.time_divisor()
+        } else {
+            (self.moves_to_go as f32).min(tunables.time_divisor())
+        }
     }
 
     pub fn check(
@@ -76,11 +126,16 @@ impl Limiters {
         tim: u128,
         nodes: u128,
         depth: u32,
+        soft_scale: f32,
         tunables: &Tunables,
         #[cfg(feature = "datagen")] new_visit_distribution: &[u32],
         #[cfg(feature = "datagen")] old_visit_distribution: &[u32],
     ) -> bool {
-        if self.use_time && tim >= self.time_allocated(tunables) {
+        if self.use_time
+            && tim
+                >= ((self.soft_time_limit(tunables) as f32 * soft_scale) as u128)
+                    .min(self.hard_time_limit(tunables))
+        {
             return false;
         }
         if self.use_nodes && nodes >= self.node_lim {
@@ -105,6 +160,77 @@ impl Limiters {
     }
 }
 
+// how many nodes between updates of the soft limit scale
+const SOFT_TIME_UPDATE_NODES: u64 = 1024;
+
+// tracks the root over the search to stretch or shrink the soft time limit,
+// lives across tree half switches so node indices aren't kept, only the move
+pub struct SoftTime {
+    next_update: u64,
+    best_move: Move,
+    best_changes: f32,
+    start_score: Option<f32>,
+    scale: f32,
+}
+
+impl SoftTime {
+    pub fn new() -> Self {
+        Self {
+            next_update: SOFT_TIME_UPDATE_NODES,
+            best_move: Move::NULL_MOVE,
+            best_changes: 0.0,
+            start_score: None,
+            scale: 1.0,
+        }
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn update_due(&mut self, nodes: u64) -> bool {
+        if nodes < self.next_update {
+            return false;
+        }
+        self.next_update = nodes + SOFT_TIME_UPDATE_NODES;
+        true
+    }
+
+    // best_score is from the root side's perspective,
+    // visit_share is the fraction of root visits the best move has
+    pub fn update(
+        &mut self,
+        best_move: Move,
+        best_score: f32,
+        visit_share: f32,
+        tunables: &Tunables,
+    ) {
+        // an unstable best move wants more time, old changes fade out
+        if self.best_move != Move::NULL_MOVE && best_move != self.best_move {
+            self.best_changes += 1.0;
+        }
+        self.best_changes *= tunables.tm_change_decay();
+        self.best_move = best_move;
+        let stability = 1.0 + tunables.tm_change_mult() * self.best_changes;
+
+        // a dominant move needs less checking
+        let share = tunables.tm_share_base() - tunables.tm_share_mult() * visit_share;
+
+        // if things look worse than when we started, look for a way out
+        let start_score = *self.start_score.get_or_insert(best_score);
+        let drop = 1.0 + tunables.tm_drop_mult() * (start_score - best_score).max(0.0);
+
+        self.scale =
+            (stability * share * drop).clamp(tunables.tm_scale_min(), tunables.tm_scale_max());
+    }
+}
+
+impl Default for SoftTime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(feature = "datagen")]
 fn calc_kld(new_visit_distribution: &[u32], old_visit_distribution: &[u32]) -> Option<f64> {
     let new_visits_sum = new_visit_distribution.iter().sum::<u32>();
@@ -132,3 +258,63 @@ fn calc_kld(new_visit_distribution: &[u32], old_visit_distribution: &[u32]) -> O
 
     Some(kld / (new_visits_sum / old_visits_sum) as f64)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiters(time: u128, moves_to_go: u32) -> Limiters {
+        let mut limiters = Limiters::new();
+        limiters.load_values(
+            time,
+            0,
+            0,
+            0,
+            0,
+            #[cfg(feature = "datagen")]
+            0.0,
+        );
+        limiters.set_moves_to_go(moves_to_go);
+        limiters
+    }
+
+    #[test]
+    fn small_moves_to_go_leaves_time_for_the_rest() {
+        let tunables = Tunables::new();
+        for moves_to_go in 2..=5 {
+            let limiters = limiters(60_000, moves_to_go);
+            let hard = limiters.hard_time_limit(&tunables);
+            let share = 60_000 / u128::from(moves_to_go);
+            assert!(hard <= share * 5 / 4, "{moves_to_go} to go: {hard}ms");
+            assert!(limiters.soft_time_limit(&tunables) <= hard);
+        }
+    }
+
+    #[test]
+    fn last_move_before_the_control_keeps_a_reserve() {
+        let tunables = Tunables::new();
+        let limiters = limiters(60_000, 1);
+        assert_eq!(limiters.hard_time_limit(&tunables), 48_000);
+    }
+
+    #[test]
+    fn moves_to_go_stops_the_search_sooner() {
+        let tunables = Tunables::new();
+        let limiters = limiters(60_000, 2);
+        let check = |tim| {
+            limiters.check(
+                tim,
+                0,
+                0,
+                tunables.tm_scale_max(),
+                &tunables,
+                #[cfg(feature = "datagen")]
+                &[],
+                #[cfg(feature = "datagen")]
+                &[],
+            )
+        };
+        assert!(check(30_000));
+        assert!(!check(40_000));
+    }
+}
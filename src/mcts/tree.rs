@@ -1,4 +1,4 @@
-use std::ops::{Index, IndexMut};
+use std::ops::Index;
 
 /*
     Anura
@@ -18,6 +18,7 @@ use std::ops::{Index, IndexMut};
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 use super::{node::Node, tree_half::TreeHalf};
+use crate::types::moves::Move;
 
 pub const DEFAULT_HASH_SIZE: usize = 64;
 
@@ -78,48 +79,73 @@ impl SearchTree {
         self.current_half = 0;
     }
 
-    pub fn push(&mut self, node: Node) -> Option<()> {
+    pub fn push(&self, mov: Move, policy: f32) -> Option<()> {
         // push node to current half
-        self.halves[self.current_half].push(node)?;
+        self.halves[self.current_half].push(mov, policy)?;
         Some(())
     }
 
-    pub fn copy_children(&mut self, parent: usize) -> Option<()> {
-        let parent_ind = parent & IND_MASK;
-        let parent_node = self.halves[self.current_half][parent_ind];
-        let child = parent_node.first_child as usize;
-        let child_half = child >> 31;
+    // claims `count` consecutive nodes in the current half, returns the index of the first one
+    pub fn reserve(&self, count: usize) -> Option<usize> {
+        let start = self.halves[self.current_half].reserve(count)?;
+        Some((self.current_half << 31) | start)
+    }
 
+    pub fn copy_children(&self, parent: usize) -> Option<()> {
+        let parent_node = &self[parent];
+        if parent_node.first_child() as usize >> 31 == self.current_half {
+            return Some(());
+        }
+
+        parent_node.lock();
+        // someone else might have copied them while we were waiting
+        let child = parent_node.first_child() as usize;
+        let child_half = child >> 31;
         if child_half == self.current_half {
+            parent_node.unlock();
             return Some(());
         }
 
-        let child_count = parent_node.child_count as usize;
+        let child_count = parent_node.child_count() as usize;
+        let Some(new_first) = self.reserve(child_count) else {
+            parent_node.unlock();
+            return None;
+        };
 
-        for this_child in child..(child + child_count) {
-            let this_child_ind = this_child & IND_MASK;
-            // this_child_ind ends up overflowing
-            let this_child_node = self.halves[child_half][this_child_ind];
-            self.halves[self.current_half].push(this_child_node)?;
+        for i in 0..child_count {
+            let this_child_ind = (child & IND_MASK) + i;
+            self[new_first + i].copy_from(&self.halves[child_half][this_child_ind]);
         }
-        self[parent].first_child = self.next() as u32 - child_count as u32;
+        parent_node.set_first_child(new_first as u32);
+        parent_node.unlock();
         Some(())
     }
 
+    // makes a node from the old tree the root, its children get moved into the current half
+    // right away so they survive the next switch even if the root is never searched again
+    pub fn promote(&self, node: usize) -> Option<()> {
+        let root = self.root_node();
+        if node != root {
+            self[root].copy_from(&self[node]);
+        }
+        self.copy_children(root)
+    }
+
     pub fn switch_halves(&mut self) {
         // switch halves
         self.current_half = 1 - self.current_half;
         self.halves[self.current_half].clear();
         self.dereference();
         // ensure root node is first in the new entry
-        self.halves[self.current_half].push(self.halves[1 - self.current_half][0]);
+        self.halves[self.current_half].reserve(1);
+        self.halves[self.current_half][0].copy_from(&self.halves[1 - self.current_half][0]);
     }
 
     pub fn dereference(&mut self) {
         for i in 0..self.half_size {
-            if self.halves[1 - self.current_half][i].first_child >> 31 == self.current_half as u32 {
-                self.halves[1 - self.current_half][i].first_child = IND_MASK as u32;
-                self.halves[1 - self.current_half][i].child_count = 0;
+            let node = &self.halves[1 - self.current_half][i];
+            if node.first_child() >> 31 == self.current_half as u32 {
+                node.set_children(IND_MASK as u32, 0);
             }
         }
     }
@@ -139,11 +165,3 @@ impl Index<usize> for SearchTree {
         &self.halves[half][ind]
     }
 }
-
-impl IndexMut<usize> for SearchTree {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        let half = index >> 31;
-        let ind = index & IND_MASK;
-        &mut self.halves[half][ind]
-    }
-}
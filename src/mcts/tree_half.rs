@@ -16,7 +16,8 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use std::ops::{Index, IndexMut, Range};
+use std::ops::{Index, Range};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::types::moves::Move;
 
@@ -24,13 +25,14 @@ use super::node::Node;
 
 pub struct TreeHalf {
     nodes: Vec<Node>,
-    length: usize,
+    // can run past the end of the half when a reservation fails
+    length: AtomicUsize,
 }
 impl TreeHalf {
     pub fn new(size: usize) -> Self {
         let mut half = Self {
             nodes: Vec::with_capacity(size),
-            length: 0,
+            length: AtomicUsize::new(0),
         };
         for _ in 0..size {
             half.nodes.push(Node::new(Move::NULL_MOVE, 0.0));
@@ -39,11 +41,11 @@ impl TreeHalf {
     }
 
     pub fn clear(&mut self) {
-        self.length = 0;
+        *self.length.get_mut() = 0;
     }
 
     pub fn len(&self) -> usize {
-        self.length
+        self.length.load(Ordering::Relaxed).min(self.nodes.len())
     }
 
     pub fn size(&self) -> usize {
@@ -58,14 +60,19 @@ impl TreeHalf {
         self.len() >= self.nodes.len()
     }
 
-    pub fn push(&mut self, node: Node) -> Option<usize> {
-        if self.is_full() {
+    // claims `count` consecutive slots, returns the first one
+    pub fn reserve(&self, count: usize) -> Option<usize> {
+        let start = self.length.fetch_add(count, Ordering::Relaxed);
+        if start + count > self.nodes.len() {
             return None;
         }
-        let len = self.length;
-        self[len] = node;
-        self.length += 1;
-        Some(len)
+        Some(start)
+    }
+
+    pub fn push(&self, mov: Move, policy: f32) -> Option<usize> {
+        let idx = self.reserve(1)?;
+        self[idx].set(mov, policy);
+        Some(idx)
     }
 }
 
@@ -76,12 +83,6 @@ impl Index<usize> for TreeHalf {
     }
 }
 
-impl IndexMut<usize> for TreeHalf {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.nodes[index]
-    }
-}
-
 impl Index<Range<usize>> for TreeHalf {
     type Output = [Node];
     fn index(&self, index: Range<usize>) -> &Self::Output {
@@ -0,0 +1,70 @@
+/*
+    Anura
+    Copyright (C) 2025 Joseph Pasfield
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// value net outputs by position hash, the tree keeps transpositions as separate nodes but
+// they only get evaluated once
+// entries don't point into the tree, so switching halves doesn't have to touch them
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::tree::DEFAULT_HASH_SIZE;
+
+// the cache gets this fraction of the tree's memory on top of it
+const TREE_FRACTION: usize = 16;
+
+// each entry is the top half of the hash and the eval packed together, so a read
+// can't see half of one write and half of another, 0 is empty
+pub struct ValueCache {
+    entries: Vec<AtomicU64>,
+}
+
+impl Default for ValueCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_SIZE)
+    }
+}
+
+impl ValueCache {
+    pub fn new(tree_mb: usize) -> Self {
+        let size = (tree_mb * 1024 * 1024 / TREE_FRACTION / size_of::<AtomicU64>()).max(1);
+        Self {
+            entries: (0..size).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for entry in &mut self.entries {
+            *entry.get_mut() = 0;
+        }
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        // the bottom half picks the slot, the top half is what gets checked
+        ((hash as u32 as u64 * self.entries.len() as u64) >> 32) as usize
+    }
+
+    pub fn get(&self, hash: u64) -> Option<i32> {
+        let entry = self.entries[self.index(hash)].load(Ordering::Relaxed);
+        (entry != 0 && entry >> 32 == hash >> 32).then_some(entry as u32 as i32)
+    }
+
+    pub fn insert(&self, hash: u64, eval: i32) {
+        let entry = (hash & !0xffff_ffff) | eval as u32 as u64;
+        self.entries[self.index(hash)].store(entry, Ordering::Relaxed);
+    }
+}
@@ -15,8 +15,6 @@
     You should have received a copy of the GNU General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
-use std::arch::x86_64::_pext_u64;
-
 use crate::types::bitboard::Bitboard;
 use crate::types::square::Square;
 
@@ -24,38 +22,91 @@ use super::lookups::{BISHOP_MASKS, ROOK_MASKS};
 
 // slidey pieces
 
-// todo: magic bitboards backup
-// would prefer to generate my own magics instead of using c#larity's again
+// pext where the cpu has it, magic bitboards (generated in build.rs) everywhere else
+// zen 1/2 do have bmi2, but their pext is microcoded and slow, so build those with --features magics
+#[cfg(all(target_arch = "x86_64", target_feature = "bmi2", not(feature = "magics")))]
+use pext::{get_bishop_index, get_rook_index, BISHOP_MOVES, ROOK_MOVES};
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "bmi2", not(feature = "magics"))))]
+use magic::{get_bishop_index, get_rook_index, BISHOP_MOVES, ROOK_MOVES};
 
 pub const MAX_ROOK_ENTRIES: usize = 4096;
 pub const MAX_BISHOP_ENTRIES: usize = 512;
 pub const ROOK_TABLE_SIZE: usize = 2097152;
 pub const BISHOP_TABLE_SIZE: usize = 262144;
 
-static ROOK_MOVES: [[u64; MAX_ROOK_ENTRIES]; 64] =
-    unsafe { std::mem::transmute(*include_bytes!("tables/rooks.bin")) };
-
-static BISHOP_MOVES: [[u64; MAX_BISHOP_ENTRIES]; 64] =
-    unsafe { std::mem::transmute(*include_bytes!("tables/bishops.bin")) };
-
 #[must_use]
 #[inline(always)]
 pub fn get_rook_attacks(sq: Square, occupied: Bitboard) -> Bitboard {
-    Bitboard(ROOK_MOVES[sq.as_usize()][get_rook_index_pext(sq, occupied)])
-}
-
-#[inline(always)]
-fn get_rook_index_pext(sq: Square, occupied: Bitboard) -> usize {
-    unsafe { _pext_u64(occupied.as_u64(), ROOK_MASKS[sq.as_usize()]) as usize }
+    Bitboard(ROOK_MOVES[sq.as_usize()][get_rook_index(sq, occupied)])
 }
 
 #[must_use]
 #[inline(always)]
 pub fn get_bishop_attacks(sq: Square, occupied: Bitboard) -> Bitboard {
-    Bitboard(BISHOP_MOVES[sq.as_usize()][get_bishop_index_pext(sq, occupied)])
+    Bitboard(BISHOP_MOVES[sq.as_usize()][get_bishop_index(sq, occupied)])
 }
 
-#[inline(always)]
-fn get_bishop_index_pext(sq: Square, occupied: Bitboard) -> usize {
-    unsafe { _pext_u64(occupied.as_u64(), BISHOP_MASKS[sq.as_usize()]) as usize }
+#[cfg(all(target_arch = "x86_64", target_feature = "bmi2", not(feature = "magics")))]
+mod pext {
+    use std::arch::x86_64::_pext_u64;
+
+    use super::{BISHOP_MASKS, MAX_BISHOP_ENTRIES, MAX_ROOK_ENTRIES, ROOK_MASKS};
+    use crate::types::bitboard::Bitboard;
+    use crate::types::square::Square;
+
+    pub static ROOK_MOVES: [[u64; MAX_ROOK_ENTRIES]; 64] =
+        unsafe { std::mem::transmute(*include_bytes!("tables/rooks.bin")) };
+
+    pub static BISHOP_MOVES: [[u64; MAX_BISHOP_ENTRIES]; 64] =
+        unsafe { std::mem::transmute(*include_bytes!("tables/bishops.bin")) };
+
+    #[inline(always)]
+    pub fn get_rook_index(sq: Square, occupied: Bitboard) -> usize {
+        unsafe { _pext_u64(occupied.as_u64(), ROOK_MASKS[sq.as_usize()]) as usize }
+    }
+
+    #[inline(always)]
+    pub fn get_bishop_index(sq: Square, occupied: Bitboard) -> usize {
+        unsafe { _pext_u64(occupied.as_u64(), BISHOP_MASKS[sq.as_usize()]) as usize }
+    }
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "bmi2", not(feature = "magics"))))]
+mod magic {
+    use super::{BISHOP_MASKS, MAX_BISHOP_ENTRIES, MAX_ROOK_ENTRIES, ROOK_MASKS};
+    use crate::types::bitboard::Bitboard;
+    use crate::types::square::Square;
+
+    // same shifts as build.rs
+    const ROOK_SHIFT: u32 = 64 - 12;
+    const BISHOP_SHIFT: u32 = 64 - 9;
+
+    pub static ROOK_MOVES: [[u64; MAX_ROOK_ENTRIES]; 64] = unsafe {
+        std::mem::transmute(*include_bytes!(concat!(env!("OUT_DIR"), "/rook_attacks.bin")))
+    };
+
+    pub static BISHOP_MOVES: [[u64; MAX_BISHOP_ENTRIES]; 64] = unsafe {
+        std::mem::transmute(*include_bytes!(concat!(env!("OUT_DIR"), "/bishop_attacks.bin")))
+    };
+
+    static ROOK_MAGICS: [u64; 64] = unsafe {
+        std::mem::transmute(*include_bytes!(concat!(env!("OUT_DIR"), "/rook_magics.bin")))
+    };
+
+    static BISHOP_MAGICS: [u64; 64] = unsafe {
+        std::mem::transmute(*include_bytes!(concat!(env!("OUT_DIR"), "/bishop_magics.bin")))
+    };
+
+    #[inline(always)]
+    pub fn get_rook_index(sq: Square, occupied: Bitboard) -> usize {
+        let relevant = occupied.as_u64() & ROOK_MASKS[sq.as_usize()];
+        (relevant.wrapping_mul(ROOK_MAGICS[sq.as_usize()]) >> ROOK_SHIFT) as usize
+    }
+
+    #[inline(always)]
+    pub fn get_bishop_index(sq: Square, occupied: Bitboard) -> usize {
+        let relevant = occupied.as_u64() & BISHOP_MASKS[sq.as_usize()];
+        (relevant.wrapping_mul(BISHOP_MAGICS[sq.as_usize()]) >> BISHOP_SHIFT) as usize
+    }
 }
@@ -15,6 +15,204 @@
     You should have received a copy of the GNU General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
+use std::{
+    alloc::{alloc_zeroed, Layout},
+    fs,
+};
+
 pub mod policy;
 mod policy_outs;
+mod simd;
 pub mod value;
+
+// value given to EvalFile / PolicyFile to go back to the net built into the binary
+pub const EMBEDDED_NET: &str = "<embedded>";
+
+// every net file starts with this, padded out to 64 bytes so the weights stay aligned
+// layout (little endian):
+// magic [u8; 4], version u16, input set u16, input size u32, hidden size u32,
+// output size u32 (buckets for value, moves for policy), QA i32, QB i32
+pub const HEADER_SIZE: usize = 64;
+pub const HEADER_VERSION: u16 = 1;
+
+// 768 piece-square inputs, times 4 for being threatened and/or defended
+pub const INPUT_SET_THREATS: u16 = 1;
+
+#[derive(Clone, Copy, Debug)]
+pub struct NetHeader {
+    pub magic: [u8; 4],
+    pub version: u16,
+    pub input_set: u16,
+    pub input_size: u32,
+    pub hl_size: u32,
+    pub output_size: u32,
+    pub qa: i32,
+    pub qb: i32,
+}
+
+const fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+const fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+impl NetHeader {
+    // const so the embedded nets can be checked at compile time
+    pub const fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_SIZE {
+            return None;
+        }
+        Some(Self {
+            magic: [bytes[0], bytes[1], bytes[2], bytes[3]],
+            version: read_u16(bytes, 4),
+            input_set: read_u16(bytes, 6),
+            input_size: read_u32(bytes, 8),
+            hl_size: read_u32(bytes, 12),
+            output_size: read_u32(bytes, 16),
+            qa: read_u32(bytes, 20) as i32,
+            qb: read_u32(bytes, 24) as i32,
+        })
+    }
+
+    pub const fn matches(&self, other: &Self) -> bool {
+        self.magic[0] == other.magic[0]
+            && self.magic[1] == other.magic[1]
+            && self.magic[2] == other.magic[2]
+            && self.magic[3] == other.magic[3]
+            && self.version == other.version
+            && self.input_set == other.input_set
+            && self.input_size == other.input_size
+            && self.hl_size == other.hl_size
+            && self.output_size == other.output_size
+            && self.qa == other.qa
+            && self.qb == other.qb
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&self.magic);
+        bytes[4..6].copy_from_slice(&self.version.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.input_set.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.input_size.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.hl_size.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.output_size.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.qa.to_le_bytes());
+        bytes[24..28].copy_from_slice(&self.qb.to_le_bytes());
+        bytes
+    }
+
+    // lists everything that differs from what this build expects
+    pub fn check(&self, expected: &Self) -> Result<(), String> {
+        if self.magic != expected.magic {
+            return Err(format!(
+                "no {} header, raw nets need `anura addheader <in> <out>` first",
+                String::from_utf8_lossy(&expected.magic)
+            ));
+        }
+        let mut mismatches = vec![];
+        let mut compare = |name: &str, found: i64, wanted: i64| {
+            if found != wanted {
+                mismatches.push(format!("{name} is {found}, this build expects {wanted}"));
+            }
+        };
+        compare(
+            "header version",
+            self.version.into(),
+            expected.version.into(),
+        );
+        compare(
+            "input set",
+            self.input_set.into(),
+            expected.input_set.into(),
+        );
+        compare(
+            "input size",
+            self.input_size.into(),
+            expected.input_size.into(),
+        );
+        compare("hidden size", self.hl_size.into(), expected.hl_size.into());
+        compare(
+            "output size",
+            self.output_size.into(),
+            expected.output_size.into(),
+        );
+        compare("QA", self.qa.into(), expected.qa.into());
+        compare("QB", self.qb.into(), expected.qb.into());
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("architecture mismatch: {}", mismatches.join(", ")))
+        }
+    }
+}
+
+// puts this build's header on a raw net straight out of the trainer, so it can be used with
+// EvalFile / PolicyFile
+pub fn add_header(input: &str, output: &str) -> Result<(), String> {
+    let (arch, size) = if input.ends_with(".vn") {
+        (
+            value::VALUE_ARCH,
+            std::mem::size_of::<value::ValueNetwork>(),
+        )
+    } else if input.ends_with(".pn") {
+        (
+            policy::POLICY_ARCH,
+            std::mem::size_of::<policy::PolicyNetwork>(),
+        )
+    } else {
+        return Err(format!("{input} is neither a .vn nor a .pn file"));
+    };
+    let bytes = fs::read(input).map_err(|e| format!("couldn't read {input}: {e}"))?;
+    if bytes.len() != size {
+        return Err(format!(
+            "{input} is {} bytes, a raw net for this build is {size}",
+            bytes.len()
+        ));
+    }
+
+    let mut file = arch.to_bytes().to_vec();
+    file.extend_from_slice(&bytes);
+    fs::write(output, file).map_err(|e| format!("couldn't write {output}: {e}"))
+}
+
+/// networks that can be filled straight from file bytes
+///
+/// # Safety
+///
+/// only for types made purely of integer arrays, where every bit pattern is valid
+unsafe trait RawNet {}
+
+unsafe impl RawNet for value::ValueNetwork {}
+unsafe impl RawNet for policy::PolicyNetwork {}
+
+// reads a net file straight into a heap allocated network, after checking its header
+fn read_net<T: RawNet>(path: &str, extension: &str, arch: &NetHeader) -> Result<Box<T>, String> {
+    if !path.ends_with(extension) {
+        return Err(format!("{path} is not a {extension} file"));
+    }
+    let bytes = fs::read(path).map_err(|e| format!("couldn't read {path}: {e}"))?;
+    let header =
+        NetHeader::parse(&bytes).ok_or_else(|| format!("{path} is too short for a net"))?;
+    header.check(arch).map_err(|e| format!("{path}: {e}"))?;
+    let weights = &bytes[HEADER_SIZE..];
+    let expected = std::mem::size_of::<T>();
+    if weights.len() != expected {
+        return Err(format!(
+            "{path} has {} bytes of weights, expected {expected}",
+            weights.len()
+        ));
+    }
+
+    // way too big for the stack, and RawNet means any bytes make a valid T
+    let layout = Layout::new::<T>();
+    unsafe {
+        let ptr = alloc_zeroed(layout).cast::<T>();
+        if ptr.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        std::ptr::copy_nonoverlapping(weights.as_ptr(), ptr.cast::<u8>(), expected);
+        Ok(Box::from_raw(ptr))
+    }
+}
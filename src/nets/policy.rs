@@ -23,10 +23,15 @@
 // quantised
 // threat inputs!
 
-use crate::nets::policy_outs::move_index;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::nets::{
+    policy_outs::move_index, read_net, simd, NetHeader, EMBEDDED_NET, HEADER_SIZE, HEADER_VERSION,
+    INPUT_SET_THREATS,
+};
 use crate::{
     board::Position,
-    types::{moves::Move, square::Square},
+    types::{moves::Move, square::Square, PolicyList},
 };
 use crate::types::bitboard::Bitboard;
 
@@ -34,7 +39,7 @@ const INPUT_SIZE: usize = 768 * 4;
 const HL_SIZE: usize = 1024;
 const OUTPUT_SIZE: usize = 1880;
 
-const QA: i16 = 128;
+pub const QA: i16 = 128;
 const QB: f32 = 128.0;
 
 #[derive(Clone, Copy, Debug)]
@@ -46,7 +51,58 @@ pub struct PolicyNetwork {
     pub l2_biases: [i8; OUTPUT_SIZE],            // [output]
 }
 
-pub static POLICY_NET: PolicyNetwork = unsafe { std::mem::transmute(*include_bytes!("apn.pn")) };
+// what a .pn header has to say for this build to accept the net
+pub const POLICY_ARCH: NetHeader = NetHeader {
+    magic: *b"ANPN",
+    version: HEADER_VERSION,
+    input_set: INPUT_SET_THREATS,
+    input_size: INPUT_SIZE as u32,
+    hl_size: HL_SIZE as u32,
+    output_size: OUTPUT_SIZE as u32,
+    qa: QA as i32,
+    qb: QB as i32,
+};
+
+// build.rs puts the header on nets that don't have one yet
+const EMBEDDED_BYTES: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/apn.pn"));
+const _: () = assert!(
+    matches!(NetHeader::parse(EMBEDDED_BYTES), Some(header) if header.matches(&POLICY_ARCH)),
+    "the embedded policy net's header doesn't match the compiled architecture"
+);
+
+#[repr(C)]
+struct PolicyNetFile {
+    _header: [u8; HEADER_SIZE],
+    net: PolicyNetwork,
+}
+
+static EMBEDDED_POLICY_NET: PolicyNetFile =
+    unsafe { std::mem::transmute(*include_bytes!(concat!(env!("OUT_DIR"), "/apn.pn"))) };
+
+// the net in use, either the embedded one or one loaded with PolicyFile
+static POLICY_NET: AtomicPtr<PolicyNetwork> =
+    AtomicPtr::new(&EMBEDDED_POLICY_NET.net as *const PolicyNetwork as *mut PolicyNetwork);
+
+pub fn policy_net() -> &'static PolicyNetwork {
+    // only ever points at the embedded net or a leaked box, both live forever
+    unsafe { &*POLICY_NET.load(Ordering::Acquire) }
+}
+
+// swaps the policy net for the one in the given .pn file (or back to the embedded one),
+// must not be called mid search
+// replaced nets are leaked, a search thread could in theory still be looking at one
+pub fn load_policy_net(path: &str) -> Result<(), String> {
+    if path == EMBEDDED_NET {
+        POLICY_NET.store(
+            &EMBEDDED_POLICY_NET.net as *const PolicyNetwork as *mut PolicyNetwork,
+            Ordering::Release,
+        );
+        return Ok(());
+    }
+    let net = read_net::<PolicyNetwork>(path, ".pn", &POLICY_ARCH)?;
+    POLICY_NET.store(Box::into_raw(net), Ordering::Release);
+    Ok(())
+}
 
 #[derive(Debug, Clone)]
 pub struct PolicyAccumulator {
@@ -65,14 +121,16 @@ const PIECE_STRIDE: usize = 64;
 impl PolicyAccumulator {
     fn new() -> Self {
         let mut l1 = [0; HL_SIZE];
+        let net = policy_net();
         for (i, hl) in l1.iter_mut().enumerate().take(HL_SIZE) {
-            *hl = POLICY_NET.l1_biases[i] as i16;
+            *hl = net.l1_biases[i] as i16;
         }
         Self { l1 }
     }
 
     pub fn load_position(&mut self, pos: &Position, ctm: u8) {
         self.clear();
+        let net = policy_net();
         let king = pos.king_sqs[ctm as usize];
         let hm = if king.0 % 8 > 3 { 7 } else { 0 };
         // threats & defenses!
@@ -95,31 +153,29 @@ impl PolicyAccumulator {
             if defences & bit != Bitboard::EMPTY {
                 input += 768 * 2;
             }
-            for hl_node in 0..HL_SIZE {
-                self.l1[hl_node] += POLICY_NET.l1_weights[input][hl_node] as i16;
-            }
+            simd::add_i8(&mut self.l1, &net.l1_weights[input]);
         }
     }
 
     pub fn clear(&mut self) {
+        let net = policy_net();
         for i in 0..HL_SIZE {
-            self.l1[i] = POLICY_NET.l1_biases[i] as i16;
+            self.l1[i] = net.l1_biases[i] as i16;
         }
     }
 
-    pub fn get_score(&self, mov: Move, ctm: u8, king: Square) -> f32 {
-        let move_index = move_index(ctm, mov, king);
-        let mut output: i32 = 0;
+    // scores every move in one go, with the activation shared between them
+    pub fn get_scores(&self, moves: &[Move], ctm: u8, king: Square, scores: &mut PolicyList) {
+        let net = policy_net();
+        // SCReLU
+        let mut activated = [0; HL_SIZE];
+        simd::screlu_i16(&self.l1, &mut activated, QA);
         // hl -> output
-        for hl_node in 0..HL_SIZE {
-            output += (POLICY_NET.l2_weights[move_index][hl_node] as i32)
-                * (activation(self.l1[hl_node]) as i32);
+        scores.clear();
+        for &mov in moves {
+            let move_index = move_index(ctm, mov, king);
+            let output = simd::dot_i8(&activated, &net.l2_weights[move_index]);
+            scores.push((output as f32 / (QA as f32 * QB) + net.l2_biases[move_index] as f32) / QB);
         }
-        (output as f32 / (QA as f32 * QB) + POLICY_NET.l2_biases[move_index] as f32) / QB
     }
 }
-
-// SCReLU
-pub fn activation(x: i16) -> i16 {
-    x.clamp(0, QA).pow(2)
-}
@@ -16,8 +16,13 @@ pub fn move_index(ctm: u8, mov: Move, king: Square) -> usize {
         OFFSETS[64] + 22 * (mov.flag() as usize - Flag::KnightPromo as usize) + promo_id as usize
     } else {
         let flipper = if ctm == 0 { 56 } else { 0 };
+        // castling is the king's move, unless (in frc) the king stays where it is
+        let to = match mov.castling_king_to() {
+            Some(king_to) if king_to.0 != mov.from() => king_to.0,
+            _ => mov.to(),
+        };
         let from = (mov.from() ^ flipper ^ hm) as usize;
-        let to = (mov.to() ^ flipper ^ hm) as usize;
+        let to = (to ^ flipper ^ hm) as usize;
 
         let below = ALL_DESTINATIONS[from] & ((1 << to) - 1);
 
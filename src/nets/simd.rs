@@ -0,0 +1,498 @@
+/*
+    Anura
+    Copyright (C) 2025 Joseph Pasfield
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// inference kernels, picked at runtime from what the cpu supports
+// every version does exactly the same (wrapping) integer math as the scalar one,
+// so the choice never changes an evaluation, only how fast it's computed
+
+use std::sync::OnceLock;
+
+// the widest kernel steps this many elements at a time and has no tail handling,
+// so every slice has to be a multiple of it long
+const LANES: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SimdLevel {
+    Scalar,
+    Avx2,
+    Avx512,
+}
+
+static LEVEL: OnceLock<SimdLevel> = OnceLock::new();
+
+pub fn simd_level() -> SimdLevel {
+    *LEVEL.get_or_init(detect)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn detect() -> SimdLevel {
+    if is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512bw") {
+        SimdLevel::Avx512
+    } else if is_x86_feature_detected!("avx2") {
+        SimdLevel::Avx2
+    } else {
+        SimdLevel::Scalar
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn detect() -> SimdLevel {
+    SimdLevel::Scalar
+}
+
+// the kernels read and write whole vectors, anything else would go out of bounds
+fn check_lengths(a: usize, b: usize) {
+    assert_eq!(a, b);
+    assert_eq!(a % LANES, 0);
+}
+
+// acc += weights
+pub fn add_i16(acc: &mut [i16], weights: &[i16]) {
+    check_lengths(acc.len(), weights.len());
+    match simd_level() {
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx512 => unsafe { x86::add_i16_avx512(acc, weights) },
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => unsafe { x86::add_i16_avx2(acc, weights) },
+        _ => scalar::add_i16(acc, weights),
+    }
+}
+
+// acc -= weights
+pub fn sub_i16(acc: &mut [i16], weights: &[i16]) {
+    check_lengths(acc.len(), weights.len());
+    match simd_level() {
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx512 => unsafe { x86::sub_i16_avx512(acc, weights) },
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => unsafe { x86::sub_i16_avx2(acc, weights) },
+        _ => scalar::sub_i16(acc, weights),
+    }
+}
+
+// acc += weights, widening the i8 weights
+pub fn add_i8(acc: &mut [i16], weights: &[i8]) {
+    check_lengths(acc.len(), weights.len());
+    match simd_level() {
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx512 => unsafe { x86::add_i8_avx512(acc, weights) },
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => unsafe { x86::add_i8_avx2(acc, weights) },
+        _ => scalar::add_i8(acc, weights),
+    }
+}
+
+// sum of clamp(x, 0, max)^2 * w, in i32 (value net, max^2 doesn't fit an i16)
+pub fn screlu_dot_i16(x: &[i16], weights: &[i16], max: i32) -> i32 {
+    check_lengths(x.len(), weights.len());
+    match simd_level() {
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx512 => unsafe { x86::screlu_dot_i16_avx512(x, weights, max) },
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => unsafe { x86::screlu_dot_i16_avx2(x, weights, max) },
+        _ => scalar::screlu_dot_i16(x, weights, max),
+    }
+}
+
+// out = clamp(x, 0, max)^2, where max^2 still fits an i16 (policy net)
+pub fn screlu_i16(x: &[i16], out: &mut [i16], max: i16) {
+    check_lengths(x.len(), out.len());
+    match simd_level() {
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx512 => unsafe { x86::screlu_i16_avx512(x, out, max) },
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => unsafe { x86::screlu_i16_avx2(x, out, max) },
+        _ => scalar::screlu_i16(x, out, max),
+    }
+}
+
+// sum of x * w, in i32
+pub fn dot_i8(x: &[i16], weights: &[i8]) -> i32 {
+    check_lengths(x.len(), weights.len());
+    match simd_level() {
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx512 => unsafe { x86::dot_i8_avx512(x, weights) },
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => unsafe { x86::dot_i8_avx2(x, weights) },
+        _ => scalar::dot_i8(x, weights),
+    }
+}
+
+// the reference every other version has to match
+mod scalar {
+    pub fn add_i16(acc: &mut [i16], weights: &[i16]) {
+        for (a, w) in acc.iter_mut().zip(weights) {
+            *a = a.wrapping_add(*w);
+        }
+    }
+
+    pub fn sub_i16(acc: &mut [i16], weights: &[i16]) {
+        for (a, w) in acc.iter_mut().zip(weights) {
+            *a = a.wrapping_sub(*w);
+        }
+    }
+
+    pub fn add_i8(acc: &mut [i16], weights: &[i8]) {
+        for (a, w) in acc.iter_mut().zip(weights) {
+            *a = a.wrapping_add(*w as i16);
+        }
+    }
+
+    pub fn screlu_dot_i16(x: &[i16], weights: &[i16], max: i32) -> i32 {
+        x.iter().zip(weights).fold(0i32, |sum, (&x, &w)| {
+            let act = (x as i32).clamp(0, max);
+            sum.wrapping_add(act.wrapping_mul(act).wrapping_mul(w as i32))
+        })
+    }
+
+    pub fn screlu_i16(x: &[i16], out: &mut [i16], max: i16) {
+        for (o, &x) in out.iter_mut().zip(x) {
+            let act = x.clamp(0, max);
+            *o = act * act;
+        }
+    }
+
+    pub fn dot_i8(x: &[i16], weights: &[i8]) -> i32 {
+        x.iter()
+            .zip(weights)
+            .fold(0i32, |sum, (&x, &w)| sum.wrapping_add(x as i32 * w as i32))
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn add_i16_avx2(acc: &mut [i16], weights: &[i16]) {
+        for i in (0..acc.len()).step_by(16) {
+            let a = _mm256_loadu_si256(acc.as_ptr().add(i).cast());
+            let w = _mm256_loadu_si256(weights.as_ptr().add(i).cast());
+            _mm256_storeu_si256(acc.as_mut_ptr().add(i).cast(), _mm256_add_epi16(a, w));
+        }
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn add_i16_avx512(acc: &mut [i16], weights: &[i16]) {
+        for i in (0..acc.len()).step_by(32) {
+            let a = _mm512_loadu_si512(acc.as_ptr().add(i).cast());
+            let w = _mm512_loadu_si512(weights.as_ptr().add(i).cast());
+            _mm512_storeu_si512(acc.as_mut_ptr().add(i).cast(), _mm512_add_epi16(a, w));
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sub_i16_avx2(acc: &mut [i16], weights: &[i16]) {
+        for i in (0..acc.len()).step_by(16) {
+            let a = _mm256_loadu_si256(acc.as_ptr().add(i).cast());
+            let w = _mm256_loadu_si256(weights.as_ptr().add(i).cast());
+            _mm256_storeu_si256(acc.as_mut_ptr().add(i).cast(), _mm256_sub_epi16(a, w));
+        }
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn sub_i16_avx512(acc: &mut [i16], weights: &[i16]) {
+        for i in (0..acc.len()).step_by(32) {
+            let a = _mm512_loadu_si512(acc.as_ptr().add(i).cast());
+            let w = _mm512_loadu_si512(weights.as_ptr().add(i).cast());
+            _mm512_storeu_si512(acc.as_mut_ptr().add(i).cast(), _mm512_sub_epi16(a, w));
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn add_i8_avx2(acc: &mut [i16], weights: &[i8]) {
+        for i in (0..acc.len()).step_by(16) {
+            let a = _mm256_loadu_si256(acc.as_ptr().add(i).cast());
+            let w = _mm256_cvtepi8_epi16(_mm_loadu_si128(weights.as_ptr().add(i).cast()));
+            _mm256_storeu_si256(acc.as_mut_ptr().add(i).cast(), _mm256_add_epi16(a, w));
+        }
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn add_i8_avx512(acc: &mut [i16], weights: &[i8]) {
+        for i in (0..acc.len()).step_by(32) {
+            let a = _mm512_loadu_si512(acc.as_ptr().add(i).cast());
+            let w = _mm512_cvtepi8_epi16(_mm256_loadu_si256(weights.as_ptr().add(i).cast()));
+            _mm512_storeu_si512(acc.as_mut_ptr().add(i).cast(), _mm512_add_epi16(a, w));
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn hsum_avx2(v: __m256i) -> i32 {
+        let sum = _mm_add_epi32(_mm256_castsi256_si128(v), _mm256_extracti128_si256(v, 1));
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b01_00_11_10));
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b10_11_00_01));
+        _mm_cvtsi128_si32(sum)
+    }
+
+    // widened to i32 lanes, since max^2 * w overflows anything smaller
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn screlu_dot_i16_avx2(x: &[i16], weights: &[i16], max: i32) -> i32 {
+        let zero = _mm256_setzero_si256();
+        let max = _mm256_set1_epi32(max);
+        let mut sum = _mm256_setzero_si256();
+        for i in (0..x.len()).step_by(8) {
+            let v = _mm256_cvtepi16_epi32(_mm_loadu_si128(x.as_ptr().add(i).cast()));
+            let w = _mm256_cvtepi16_epi32(_mm_loadu_si128(weights.as_ptr().add(i).cast()));
+            let v = _mm256_min_epi32(_mm256_max_epi32(v, zero), max);
+            let v = _mm256_mullo_epi32(_mm256_mullo_epi32(v, v), w);
+            sum = _mm256_add_epi32(sum, v);
+        }
+        hsum_avx2(sum)
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn screlu_dot_i16_avx512(x: &[i16], weights: &[i16], max: i32) -> i32 {
+        let zero = _mm512_setzero_si512();
+        let max = _mm512_set1_epi32(max);
+        let mut sum = _mm512_setzero_si512();
+        for i in (0..x.len()).step_by(16) {
+            let v = _mm512_cvtepi16_epi32(_mm256_loadu_si256(x.as_ptr().add(i).cast()));
+            let w = _mm512_cvtepi16_epi32(_mm256_loadu_si256(weights.as_ptr().add(i).cast()));
+            let v = _mm512_min_epi32(_mm512_max_epi32(v, zero), max);
+            let v = _mm512_mullo_epi32(_mm512_mullo_epi32(v, v), w);
+            sum = _mm512_add_epi32(sum, v);
+        }
+        _mm512_reduce_add_epi32(sum)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn screlu_i16_avx2(x: &[i16], out: &mut [i16], max: i16) {
+        let zero = _mm256_setzero_si256();
+        let max = _mm256_set1_epi16(max);
+        for i in (0..x.len()).step_by(16) {
+            let v = _mm256_loadu_si256(x.as_ptr().add(i).cast());
+            let v = _mm256_min_epi16(_mm256_max_epi16(v, zero), max);
+            _mm256_storeu_si256(out.as_mut_ptr().add(i).cast(), _mm256_mullo_epi16(v, v));
+        }
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn screlu_i16_avx512(x: &[i16], out: &mut [i16], max: i16) {
+        let zero = _mm512_setzero_si512();
+        let max = _mm512_set1_epi16(max);
+        for i in (0..x.len()).step_by(32) {
+            let v = _mm512_loadu_si512(x.as_ptr().add(i).cast());
+            let v = _mm512_min_epi16(_mm512_max_epi16(v, zero), max);
+            _mm512_storeu_si512(out.as_mut_ptr().add(i).cast(), _mm512_mullo_epi16(v, v));
+        }
+    }
+
+    // madd pairs the i16 products up into i32s
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn dot_i8_avx2(x: &[i16], weights: &[i8]) -> i32 {
+        let mut sum = _mm256_setzero_si256();
+        for i in (0..x.len()).step_by(16) {
+            let v = _mm256_loadu_si256(x.as_ptr().add(i).cast());
+            let w = _mm256_cvtepi8_epi16(_mm_loadu_si128(weights.as_ptr().add(i).cast()));
+            sum = _mm256_add_epi32(sum, _mm256_madd_epi16(v, w));
+        }
+        hsum_avx2(sum)
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn dot_i8_avx512(x: &[i16], weights: &[i8]) -> i32 {
+        let mut sum = _mm512_setzero_si512();
+        for i in (0..x.len()).step_by(32) {
+            let v = _mm512_loadu_si512(x.as_ptr().add(i).cast());
+            let w = _mm512_cvtepi8_epi16(_mm256_loadu_si256(weights.as_ptr().add(i).cast()));
+            sum = _mm512_add_epi32(sum, _mm512_madd_epi16(v, w));
+        }
+        _mm512_reduce_add_epi32(sum)
+    }
+}
+
+// the kernels only differ from the scalar versions on x86
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+    use super::*;
+    use crate::nets::{policy, value};
+
+    // xorshift, the values only have to cover the whole range
+    fn random(seed: &mut u64) -> u64 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        *seed
+    }
+
+    fn random_i16(len: usize, seed: &mut u64) -> Vec<i16> {
+        (0..len).map(|_| random(seed) as i16).collect()
+    }
+
+    fn random_i8(len: usize, seed: &mut u64) -> Vec<i8> {
+        (0..len).map(|_| random(seed) as i8).collect()
+    }
+
+    // the vector versions this cpu can run, scalar is what they're checked against
+    fn levels() -> Vec<SimdLevel> {
+        let mut levels = vec![];
+        if is_x86_feature_detected!("avx2") {
+            levels.push(SimdLevel::Avx2);
+        }
+        if is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512bw") {
+            levels.push(SimdLevel::Avx512);
+        }
+        levels
+    }
+
+    const LENGTHS: [usize; 3] = [64, 128, 1024];
+
+    #[test]
+    fn add_i16_matches_scalar() {
+        let mut seed = 1;
+        for level in levels() {
+            for len in LENGTHS {
+                let acc = random_i16(len, &mut seed);
+                let weights = random_i16(len, &mut seed);
+                let mut expected = acc.clone();
+                scalar::add_i16(&mut expected, &weights);
+                let mut result = acc.clone();
+                unsafe {
+                    match level {
+                        SimdLevel::Avx2 => x86::add_i16_avx2(&mut result, &weights),
+                        SimdLevel::Avx512 => x86::add_i16_avx512(&mut result, &weights),
+                        SimdLevel::Scalar => unreachable!(),
+                    }
+                }
+                assert_eq!(result, expected, "{level:?}, {len} long");
+            }
+        }
+    }
+
+    #[test]
+    fn sub_i16_matches_scalar() {
+        let mut seed = 2;
+        for level in levels() {
+            for len in LENGTHS {
+                let acc = random_i16(len, &mut seed);
+                let weights = random_i16(len, &mut seed);
+                let mut expected = acc.clone();
+                scalar::sub_i16(&mut expected, &weights);
+                let mut result = acc.clone();
+                unsafe {
+                    match level {
+                        SimdLevel::Avx2 => x86::sub_i16_avx2(&mut result, &weights),
+                        SimdLevel::Avx512 => x86::sub_i16_avx512(&mut result, &weights),
+                        SimdLevel::Scalar => unreachable!(),
+                    }
+                }
+                assert_eq!(result, expected, "{level:?}, {len} long");
+            }
+        }
+    }
+
+    #[test]
+    fn add_i8_matches_scalar() {
+        let mut seed = 3;
+        for level in levels() {
+            for len in LENGTHS {
+                let acc = random_i16(len, &mut seed);
+                let weights = random_i8(len, &mut seed);
+                let mut expected = acc.clone();
+                scalar::add_i8(&mut expected, &weights);
+                let mut result = acc.clone();
+                unsafe {
+                    match level {
+                        SimdLevel::Avx2 => x86::add_i8_avx2(&mut result, &weights),
+                        SimdLevel::Avx512 => x86::add_i8_avx512(&mut result, &weights),
+                        SimdLevel::Scalar => unreachable!(),
+                    }
+                }
+                assert_eq!(result, expected, "{level:?}, {len} long");
+            }
+        }
+    }
+
+    #[test]
+    fn screlu_dot_i16_matches_scalar() {
+        let mut seed = 4;
+        for level in levels() {
+            for len in LENGTHS {
+                let x = random_i16(len, &mut seed);
+                let weights = random_i16(len, &mut seed);
+                let max = value::QA;
+                let expected = scalar::screlu_dot_i16(&x, &weights, max);
+                let result = unsafe {
+                    match level {
+                        SimdLevel::Avx2 => x86::screlu_dot_i16_avx2(&x, &weights, max),
+                        SimdLevel::Avx512 => x86::screlu_dot_i16_avx512(&x, &weights, max),
+                        SimdLevel::Scalar => unreachable!(),
+                    }
+                };
+                assert_eq!(result, expected, "{level:?}, {len} long");
+            }
+        }
+    }
+
+    #[test]
+    fn screlu_i16_matches_scalar() {
+        let mut seed = 5;
+        for level in levels() {
+            for len in LENGTHS {
+                let x = random_i16(len, &mut seed);
+                // its square still fits an i16
+                let max = policy::QA;
+                let mut expected = vec![0; len];
+                scalar::screlu_i16(&x, &mut expected, max);
+                let mut result = vec![0; len];
+                unsafe {
+                    match level {
+                        SimdLevel::Avx2 => x86::screlu_i16_avx2(&x, &mut result, max),
+                        SimdLevel::Avx512 => x86::screlu_i16_avx512(&x, &mut result, max),
+                        SimdLevel::Scalar => unreachable!(),
+                    }
+                }
+                assert_eq!(result, expected, "{level:?}, {len} long");
+            }
+        }
+    }
+
+    #[test]
+    fn dot_i8_matches_scalar() {
+        let mut seed = 6;
+        for level in levels() {
+            for len in LENGTHS {
+                let x = random_i16(len, &mut seed);
+                let weights = random_i8(len, &mut seed);
+                let expected = scalar::dot_i8(&x, &weights);
+                let result = unsafe {
+                    match level {
+                        SimdLevel::Avx2 => x86::dot_i8_avx2(&x, &weights),
+                        SimdLevel::Avx512 => x86::dot_i8_avx512(&x, &weights),
+                        SimdLevel::Scalar => unreachable!(),
+                    }
+                };
+                assert_eq!(result, expected, "{level:?}, {len} long");
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn partial_vector_is_rejected() {
+        let mut acc = vec![0; 65];
+        add_i16(&mut acc, &[1; 65]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn mismatched_lengths_are_rejected() {
+        dot_i8(&[0; 64], &[0; 128]);
+    }
+}
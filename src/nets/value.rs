@@ -16,9 +16,16 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use arrayvec::ArrayVec;
+
 use crate::{
     board::Position,
     mcts::search::EVAL_SCALE,
+    nets::{
+        read_net, simd, NetHeader, EMBEDDED_NET, HEADER_SIZE, HEADER_VERSION, INPUT_SET_THREATS,
+    },
     types::{bitboard::Bitboard, piece::Piece, square::Square},
 };
 // value net:
@@ -33,7 +40,7 @@ const OUTPUT_BUCKET_COUNT: usize = 16;
 
 const COLOR_STRIDE: usize = 64 * 6;
 const PIECE_STRIDE: usize = 64;
-const QA: i32 = 256;
+pub const QA: i32 = 256;
 const _QB: i32 = 64;
 const QAB: i32 = QA * _QB;
 
@@ -47,7 +54,58 @@ pub struct ValueNetwork {
     output_bias: [i16; OUTPUT_BUCKET_COUNT],
 }
 
-pub static VALUE_NET: ValueNetwork = unsafe { std::mem::transmute(*include_bytes!("avn.vn")) };
+// what a .vn header has to say for this build to accept the net
+pub const VALUE_ARCH: NetHeader = NetHeader {
+    magic: *b"ANVN",
+    version: HEADER_VERSION,
+    input_set: INPUT_SET_THREATS,
+    input_size: INPUT_SIZE as u32,
+    hl_size: HL_SIZE as u32,
+    output_size: OUTPUT_BUCKET_COUNT as u32,
+    qa: QA,
+    qb: _QB,
+};
+
+// build.rs puts the header on nets that don't have one yet
+const EMBEDDED_BYTES: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/avn.vn"));
+const _: () = assert!(
+    matches!(NetHeader::parse(EMBEDDED_BYTES), Some(header) if header.matches(&VALUE_ARCH)),
+    "the embedded value net's header doesn't match the compiled architecture"
+);
+
+#[repr(C)]
+struct ValueNetFile {
+    _header: [u8; HEADER_SIZE],
+    net: ValueNetwork,
+}
+
+static EMBEDDED_VALUE_NET: ValueNetFile =
+    unsafe { std::mem::transmute(*include_bytes!(concat!(env!("OUT_DIR"), "/avn.vn"))) };
+
+// the net in use, either the embedded one or one loaded with EvalFile
+static VALUE_NET: AtomicPtr<ValueNetwork> =
+    AtomicPtr::new(&EMBEDDED_VALUE_NET.net as *const ValueNetwork as *mut ValueNetwork);
+
+pub fn value_net() -> &'static ValueNetwork {
+    // only ever points at the embedded net or a leaked box, both live forever
+    unsafe { &*VALUE_NET.load(Ordering::Acquire) }
+}
+
+// swaps the value net for the one in the given .vn file (or back to the embedded one),
+// must not be called mid search
+// replaced nets are leaked, a search thread could in theory still be looking at one
+pub fn load_value_net(path: &str) -> Result<(), String> {
+    if path == EMBEDDED_NET {
+        VALUE_NET.store(
+            &EMBEDDED_VALUE_NET.net as *const ValueNetwork as *mut ValueNetwork,
+            Ordering::Release,
+        );
+        return Ok(());
+    }
+    let net = read_net::<ValueNetwork>(path, ".vn", &VALUE_ARCH)?;
+    VALUE_NET.store(Box::into_raw(net), Ordering::Release);
+    Ok(())
+}
 
 const OUTPUT_BUCKET_DIVISOR: usize = 32_usize.div_ceil(OUTPUT_BUCKET_COUNT);
 
@@ -55,11 +113,6 @@ const fn get_output_bucket(piece_count: usize) -> usize {
     (piece_count - 2) / OUTPUT_BUCKET_DIVISOR
 }
 
-#[derive(Debug, Clone)]
-pub struct ValueNetworkState {
-    state: [i16; HL_SIZE],
-}
-
 pub fn get_feature_index(
     piece: Piece,
     mut sq: Square,
@@ -90,60 +143,123 @@ pub fn get_feature_index(
     idx
 }
 
-pub fn activation(x: i16) -> i32 {
-    (x as i32).clamp(0, QA).pow(2)
+// at most 32 pieces on the board, one feature each
+type FeatureList = ArrayVec<u16, 32>;
+
+fn active_features(position: &Position, ctm: u8) -> FeatureList {
+    let king = position.king_sqs[ctm as usize];
+    let defences = position.threats_by(ctm);
+    let threats = position.threats_by(ctm ^ 1);
+
+    let mut features = FeatureList::new();
+    let mut occ = position.occupied();
+    while occ != Bitboard::EMPTY {
+        let sq = Square(occ.pop_lsb());
+        let piece = position.piece_on_square(sq);
+        features.push(get_feature_index(piece, sq, ctm, king, defences, threats) as u16);
+    }
+    features.sort_unstable();
+    features
 }
 
-impl ValueNetworkState {
-    pub fn new() -> Self {
+// an accumulator along with the (sorted) features that are in it
+#[derive(Debug, Clone)]
+struct CachedAccumulator {
+    state: [i16; HL_SIZE],
+    features: FeatureList,
+}
+
+impl CachedAccumulator {
+    fn new() -> Self {
         Self {
-            state: VALUE_NET.feature_biases,
+            state: value_net().feature_biases,
+            features: FeatureList::new(),
         }
     }
 
-    pub fn reset(&mut self) {
-        self.state = VALUE_NET.feature_biases
+    // features to add and remove to get from this accumulator to the given features
+    fn diff(&self, features: &FeatureList) -> (FeatureList, FeatureList) {
+        let mut added = FeatureList::new();
+        let mut removed = FeatureList::new();
+        let (mut old, mut new) = (0, 0);
+        loop {
+            match (self.features.get(old), features.get(new)) {
+                (Some(&a), Some(&b)) if a == b => {
+                    old += 1;
+                    new += 1;
+                }
+                (Some(&a), Some(&b)) if b < a => {
+                    added.push(b);
+                    new += 1;
+                }
+                (Some(&a), _) => {
+                    removed.push(a);
+                    old += 1;
+                }
+                (None, Some(&b)) => {
+                    added.push(b);
+                    new += 1;
+                }
+                (None, None) => break,
+            }
+        }
+        (added, removed)
     }
 
-    pub fn evaluate(&mut self, position: &Position, ctm: u8) -> i32 {
-        self.reset();
-        self.load_position(position, ctm);
-        self.forward(position.occupied().popcount() as usize)
-    }
+    // moves the accumulator to the new feature set by only adding and removing the difference,
+    // unless that's more work than starting over
+    fn update(&mut self, features: FeatureList) {
+        let (mut added, mut removed) = self.diff(&features);
 
-    pub fn load_position(&mut self, position: &Position, ctm: u8) {
-        let king = position.king_sqs[ctm as usize];
-        let defences = position.threats_by(ctm);
-        let threats = position.threats_by(ctm ^ 1);
-
-        let mut occ = position.occupied();
-        while occ != Bitboard::EMPTY {
-            let idx = Square(occ.pop_lsb());
-            let piece = position.piece_on_square(idx);
-            self.activate_feature(piece, idx, ctm, king, defences, threats);
+        let net = value_net();
+        if added.len() + removed.len() > features.len() {
+            self.state = net.feature_biases;
+            added = features.clone();
+            removed.clear();
+        }
+        for &idx in &removed {
+            let weights = &net.feature_weights[idx as usize * HL_SIZE..][..HL_SIZE];
+            simd::sub_i16(&mut self.state, weights);
         }
+        for &idx in &added {
+            let weights = &net.feature_weights[idx as usize * HL_SIZE..][..HL_SIZE];
+            simd::add_i16(&mut self.state, weights);
+        }
+        self.features = features;
     }
+}
+
+// the last accumulator for each perspective (side to move, and which half their king is on),
+// evaluating only applies the features that changed since the last position seen from there
+#[derive(Debug, Clone)]
+pub struct ValueNetworkState {
+    cache: [CachedAccumulator; 4],
+}
 
-    pub fn activate_feature(&mut self, piece: Piece, sq: Square, ctm: u8, king: Square, defences: Bitboard, threats: Bitboard) {
-        let idx = get_feature_index(piece, sq, ctm, king, defences, threats);
-        for hl_node in 0..HL_SIZE {
-            self.state[hl_node] += VALUE_NET.feature_weights[idx * HL_SIZE + hl_node];
+impl ValueNetworkState {
+    pub fn new() -> Self {
+        Self {
+            cache: std::array::from_fn(|_| CachedAccumulator::new()),
         }
     }
 
+    pub fn evaluate(&mut self, position: &Position, ctm: u8) -> i32 {
+        let king = position.king_sqs[ctm as usize];
+        let entry = &mut self.cache[ctm as usize * 2 + (king.file() > 3) as usize];
+        entry.update(active_features(position, ctm));
+        forward(&entry.state, position.occupied().popcount() as usize)
+    }
+}
 
-    pub fn forward(&self, piece_count: usize) -> i32 {
-        let mut sum = 0;
-        let output_bucket = get_output_bucket(piece_count);
-        let bucket_increment = HL_SIZE * output_bucket;
+fn forward(state: &[i16; HL_SIZE], piece_count: usize) -> i32 {
+    let output_bucket = get_output_bucket(piece_count);
+    let bucket_increment = HL_SIZE * output_bucket;
+    let net = value_net();
 
-        for hl_node in 0..HL_SIZE {
-            sum += activation(self.state[hl_node])
-                * VALUE_NET.output_weights[hl_node + bucket_increment] as i32;
-        }
+    let weights = &net.output_weights[bucket_increment..][..HL_SIZE];
+    let sum = simd::screlu_dot_i16(state, weights, QA);
 
-        (sum / QA + VALUE_NET.output_bias[output_bucket] as i32) * EVAL_SCALE as i32 / QAB
-    }
+    (sum / QA + net.output_bias[output_bucket] as i32) * EVAL_SCALE as i32 / QAB
 }
 
 impl Default for ValueNetworkState {
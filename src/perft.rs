@@ -16,7 +16,7 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use std::time::Instant;
+use std::{fs, time::Instant};
 
 use crate::{board::Board, types::MoveList};
 
@@ -895,6 +895,70 @@ pub fn run_perft_suite() {
                 6,
                 92_395_277,
             ),
+            // chess960
+            PerftTest::new(
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                4,
+                326_672,
+            ),
+            PerftTest::new(
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                4,
+                667_366,
+            ),
+            PerftTest::new(
+                "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                4,
+                273_318,
+            ),
+            PerftTest::new(
+                "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+                4,
+                382_958,
+            ),
+            PerftTest::new(
+                "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+                4,
+                1_171_749,
+            ),
+            PerftTest::new(
+                "qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9",
+                4,
+                824_055,
+            ),
+            PerftTest::new(
+                "q1bnrkr1/ppppp2p/2n2p2/4b1p1/2NP4/8/PPP1PPPP/QNB1RRKB w ge - 1 9",
+                4,
+                732_757,
+            ),
+            PerftTest::new(
+                "qbn1brkr/ppp1p1p1/2n4p/3p1p2/P7/6PP/QPPPPP2/1BNNBRKR w HFhf - 0 9",
+                4,
+                465_806,
+            ),
+            PerftTest::new(
+                "qnnbbrkr/1p2ppp1/2pp3p/p7/1P5P/2NP4/P1P1PPP1/Q1NBBRKR w HFhf - 0 9",
+                4,
+                384_260,
+            ),
+            // double fischer random, and castling where the king or rook doesn't move
+            PerftTest::new(
+                "rkrnbbqn/pppppppp/8/8/8/8/PPPPPPPP/NRKBBQRN w GBca - 0 1",
+                4,
+                151_971,
+            ),
+            PerftTest::new(
+                "rk4r1/pppppppp/8/8/8/8/PPPPPPPP/RK4R1 w GAga - 0 1",
+                4,
+                316_079,
+            ),
+            PerftTest::new(
+                "r5kr/pppppppp/8/8/8/8/PPPPPPPP/R5KR w HAha - 0 1",
+                4,
+                318_326,
+            ),
+            PerftTest::new("4k3/8/8/8/8/8/8/qR2K2R w HB - 0 1", 4, 142_883),
+            PerftTest::new("1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1", 4, 272_627),
         ]
         .to_vec()
     } else {
@@ -906,7 +970,40 @@ pub fn run_perft_suite() {
         .to_vec()
     };
     println!("tests loaded");
+    run_tests(test_suite);
+}
 
+// runs a perft file in the usual epd format, a fen and then its counts: <fen> ;D1 20 ;D2 400 ...
+// the full 960 suite (fischer.epd) is too big to keep in here
+pub fn run_perft_file(path: &str, max_depth: u8) -> Result<(), String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("couldn't read {path}: {e}"))?;
+    let mut test_suite = vec![];
+    for (line_number, line) in contents.lines().enumerate() {
+        let mut fields = line.split(';');
+        let fen = fields.next().unwrap_or_default().trim();
+        if fen.is_empty() {
+            continue;
+        }
+        for field in fields {
+            let field = field.trim();
+            let invalid = || format!("{path}:{}: invalid perft count '{field}'", line_number + 1);
+            let (depth, nodes) = field.split_once(' ').ok_or_else(invalid)?;
+            let depth: u8 = depth
+                .strip_prefix('D')
+                .and_then(|depth| depth.parse().ok())
+                .ok_or_else(invalid)?;
+            let nodes: u64 = nodes.trim().parse().map_err(|_| invalid())?;
+            if depth <= max_depth {
+                test_suite.push(PerftTest::new(fen, depth, nodes));
+            }
+        }
+    }
+    println!("{} tests loaded", test_suite.len());
+    run_tests(test_suite);
+    Ok(())
+}
+
+fn run_tests(test_suite: Vec<PerftTest>) {
     let mut total: u64 = 0;
     let start = Instant::now();
     let mut i = 0;
@@ -915,7 +1012,11 @@ pub fn run_perft_suite() {
     for test in test_suite {
         i += 1;
         let mut board: Board = Board::default();
-        board.load_fen(&test.fen);
+        if let Err(e) = board.load_fen(&test.fen) {
+            println!("test {i} failed, position {}: {e}", test.fen);
+            failed += 1;
+            continue;
+        }
         let nodes: u64 = perft(&mut board, test.depth);
         total += nodes;
         if nodes == test.nodes {
@@ -0,0 +1,1440 @@
+/*
+    Anura
+    Copyright (C) 2025 Joseph Pasfield
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// syzygy tablebase probing, a port of Ronald de Man's probing code (by way of stockfish's tbprobe)
+// wdl gets probed in the tree, dtz only at the root to pick which moves are worth searching
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    ops::Deref,
+    ptr, slice,
+    sync::{
+        atomic::{AtomicPtr, Ordering},
+        OnceLock,
+    },
+};
+
+use crate::{
+    board::Board,
+    mcts::node::GameResult,
+    movegen::others::get_king_attacks,
+    types::{
+        bitboard::Bitboard,
+        moves::{Flag, Move},
+        piece::{Piece, Types},
+        square::Square,
+        MoveList,
+    },
+};
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+const MAX_PIECES: usize = 7;
+const MAX_DTZ: i32 = 1 << 18;
+
+// wdl scores from the side to move's point of view, cursed wins and blessed losses
+// are wins and losses that the 50 move rule turns into draws
+const LOSS: i32 = -2;
+const BLESSED_LOSS: i32 = -1;
+const DRAW: i32 = 0;
+const CURSED_WIN: i32 = 1;
+const WIN: i32 = 2;
+
+// per table flags
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+// per file flags
+const SPLIT: u8 = 1;
+const HAS_PAWNS: u8 = 2;
+
+// the tablebases in use, null until SyzygyPath is set
+static TABLEBASES: AtomicPtr<Tablebases> = AtomicPtr::new(ptr::null_mut());
+
+pub fn tablebases() -> Option<&'static Tablebases> {
+    // only ever null or a leaked box, which lives forever
+    unsafe { TABLEBASES.load(Ordering::Acquire).as_ref() }
+}
+
+// looks for tables in the given directories (split like PATH), an empty path turns probing off
+// must not be called mid search, replaced tablebases are leaked like replaced nets
+pub fn load_tablebases(path: &str) -> Result<usize, String> {
+    if path.is_empty() || path == "<empty>" {
+        TABLEBASES.store(ptr::null_mut(), Ordering::Release);
+        return Ok(0);
+    }
+    let tablebases = Tablebases::new(path)?;
+    let count = tablebases.wdl.len();
+    TABLEBASES.store(Box::into_raw(Box::new(tablebases)), Ordering::Release);
+    Ok(count)
+}
+
+// the lookup tables used to turn a position into an index, same for every table
+struct Encoding {
+    map_pawns: [usize; 64],
+    map_b1h1h7: [usize; 64],
+    map_a1d1d4: [usize; 64],
+    map_kk: [[usize; 64]; 10],
+    binomial: [[u64; 64]; 6],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+// how far below (negative) or above the a1-h8 diagonal a square is
+const fn off_a1h8(sq: usize) -> i32 {
+    (sq / 8) as i32 - (sq % 8) as i32
+}
+
+impl Encoding {
+    fn new() -> Self {
+        let mut encoding = Self {
+            map_pawns: [0; 64],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 6],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        // squares below the a1-h8 diagonal to 0..27
+        let mut code = 0;
+        for sq in 0..64 {
+            if off_a1h8(sq) < 0 {
+                encoding.map_b1h1h7[sq] = code;
+                code += 1;
+            }
+        }
+
+        // the a1-d1-d4 triangle to 0..9, the diagonal goes last
+        let mut diagonal = vec![];
+        code = 0;
+        for sq in 0..28 {
+            if off_a1h8(sq) < 0 && sq % 8 <= 3 {
+                encoding.map_a1d1d4[sq] = code;
+                code += 1;
+            } else if off_a1h8(sq) == 0 && sq % 8 <= 3 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            encoding.map_a1d1d4[sq] = code;
+            code += 1;
+        }
+
+        // the 462 legal ways to place two kings with the first one in the triangle,
+        // if the first is on the diagonal the second can't be above it
+        let mut both_on_diagonal = vec![];
+        code = 0;
+        for idx in 0..10 {
+            for sq1 in 0..28 {
+                // b1 is the only square mapped to 0
+                if encoding.map_a1d1d4[sq1] != idx || (idx == 0 && sq1 != 1) {
+                    continue;
+                }
+                let king_zone = get_king_attacks(Square(sq1 as u8)).0 | (1 << sq1);
+                for sq2 in 0..64 {
+                    if king_zone & (1 << sq2) != 0 || (off_a1h8(sq1) == 0 && off_a1h8(sq2) > 0) {
+                        continue;
+                    }
+                    if off_a1h8(sq1) == 0 && off_a1h8(sq2) == 0 {
+                        both_on_diagonal.push((idx, sq2));
+                    } else {
+                        encoding.map_kk[idx][sq2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, sq2) in both_on_diagonal {
+            encoding.map_kk[idx][sq2] = code;
+            code += 1;
+        }
+
+        // binomial[k][n] is the number of ways to pick k things out of n
+        encoding.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..6.min(n + 1) {
+                let with = if k > 0 {
+                    encoding.binomial[k - 1][n - 1]
+                } else {
+                    0
+                };
+                let without = if k < n {
+                    encoding.binomial[k][n - 1]
+                } else {
+                    0
+                };
+                encoding.binomial[k][n] = with + without;
+            }
+        }
+
+        // a2-h7 to 0..47, the leading pawn is the one with the highest value,
+        // nearest to the edge and then on the lowest rank
+        let mut available = 47;
+        for lead_pawns in 1..6 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let sq = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        encoding.map_pawns[sq] = available;
+                        encoding.map_pawns[sq ^ 7] = available.saturating_sub(1);
+                        available = available.saturating_sub(2);
+                    }
+                    encoding.lead_pawn_idx[lead_pawns][sq] = idx;
+                    idx += encoding.binomial[lead_pawns - 1][encoding.map_pawns[sq]];
+                }
+                encoding.lead_pawns_size[lead_pawns][file] = idx;
+            }
+        }
+
+        encoding
+    }
+}
+
+fn encoding() -> &'static Encoding {
+    static ENCODING: OnceLock<Encoding> = OnceLock::new();
+    ENCODING.get_or_init(Encoding::new)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TableType {
+    Wdl,
+    Dtz,
+}
+
+// what a dtz table said, it only stores one side to move
+enum DtzProbe {
+    Value(i32),
+    ChangeStm,
+}
+
+// one compressed table, there's one per side to move and per leading pawn file
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    block_size: usize,
+    span: u64,
+    num_blocks: usize,
+    min_sym_len: usize,
+    // offsets into the file from here on
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    block_length_size: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    data: usize,
+    base64: Vec<u64>,
+    // how many values (minus one) each huffman symbol expands to
+    symlen: Vec<u8>,
+    // the order pieces are encoded in, using the tablebase piece codes
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    group_len: [usize; MAX_PIECES + 1],
+    // dtz only, where the value map for each wdl outcome starts
+    map_idx: [usize; 4],
+}
+
+// a table file, mapped and its header parsed when SyzygyPath is set
+struct Table {
+    table_type: TableType,
+    // KQvKQ and the like, only white to move is stored
+    symmetric: bool,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    piece_count: usize,
+    // the leading side (the one with fewer pawns) first
+    pawn_count: [usize; 2],
+    // only None while the header is being parsed
+    data: Option<TableData>,
+}
+
+struct TableData {
+    bytes: Mapping,
+    // [side to move][leading pawn file]
+    pairs: Vec<Vec<PairsData>>,
+    // dtz only, the start of the value maps
+    map: usize,
+}
+
+// a table file mapped into memory, the os only reads in the parts that get probed, so even
+// big tables cost nothing until they're used
+struct Mapping {
+    ptr: *const u8,
+    len: usize,
+}
+
+// read only, and only unmapped once nothing can reach it
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn new(path: &str) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let len = usize::try_from(file.metadata().ok()?.len()).ok()?;
+        // an empty file can't be mapped, and isn't a table anyway
+        if len == 0 {
+            return None;
+        }
+        let ptr = unsafe { sys::map(&file, len)? };
+        Some(Self { ptr, len })
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { sys::unmap(self.ptr, self.len) }
+    }
+}
+
+// the mapping stays valid after the file is closed on both
+#[cfg(unix)]
+mod sys {
+    use std::{
+        ffi::{c_int, c_long, c_void},
+        fs::File,
+        os::unix::io::AsRawFd,
+        ptr,
+    };
+
+    const PROT_READ: c_int = 1;
+    const MAP_SHARED: c_int = 1;
+
+    extern "C" {
+        fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: c_long,
+        ) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+
+    pub unsafe fn map(file: &File, len: usize) -> Option<*const u8> {
+        let ptr = mmap(
+            ptr::null_mut(),
+            len,
+            PROT_READ,
+            MAP_SHARED,
+            file.as_raw_fd(),
+            0,
+        );
+        // MAP_FAILED is -1
+        (ptr as usize != usize::MAX).then_some(ptr.cast())
+    }
+
+    pub unsafe fn unmap(ptr: *const u8, len: usize) {
+        munmap(ptr.cast_mut().cast(), len);
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{ffi::c_void, fs::File, os::windows::io::AsRawHandle, ptr};
+
+    const PAGE_READONLY: u32 = 2;
+    const FILE_MAP_READ: u32 = 4;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreateFileMappingW(
+            file: *mut c_void,
+            attributes: *mut c_void,
+            protect: u32,
+            size_high: u32,
+            size_low: u32,
+            name: *const u16,
+        ) -> *mut c_void;
+        fn MapViewOfFile(
+            mapping: *mut c_void,
+            access: u32,
+            offset_high: u32,
+            offset_low: u32,
+            len: usize,
+        ) -> *mut c_void;
+        fn UnmapViewOfFile(address: *const c_void) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    pub unsafe fn map(file: &File, _len: usize) -> Option<*const u8> {
+        let mapping = CreateFileMappingW(
+            file.as_raw_handle(),
+            ptr::null_mut(),
+            PAGE_READONLY,
+            0,
+            0,
+            ptr::null(),
+        );
+        if mapping.is_null() {
+            return None;
+        }
+        let ptr = MapViewOfFile(mapping, FILE_MAP_READ, 0, 0, 0);
+        // the view keeps the mapping alive
+        CloseHandle(mapping);
+        (!ptr.is_null()).then_some(ptr.cast())
+    }
+
+    pub unsafe fn unmap(ptr: *const u8, _len: usize) {
+        UnmapViewOfFile(ptr.cast());
+    }
+}
+
+// nothing to map files with, so no tables
+#[cfg(not(any(unix, windows)))]
+mod sys {
+    use std::fs::File;
+
+    pub unsafe fn map(_file: &File, _len: usize) -> Option<*const u8> {
+        None
+    }
+
+    pub unsafe fn unmap(_ptr: *const u8, _len: usize) {}
+}
+
+fn read_u8(bytes: &[u8], offset: usize) -> Option<u8> {
+    bytes.get(offset).copied()
+}
+
+fn read_u16_le(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32_le(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32_be(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64_be(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+// the two symbols a huffman symbol expands to, packed into 3 bytes
+fn btree_left(bytes: &[u8], d: &PairsData, sym: usize) -> Option<usize> {
+    let lr = bytes.get(d.btree + 3 * sym..d.btree + 3 * sym + 3)?;
+    Some((((lr[1] & 0xf) as usize) << 8) | lr[0] as usize)
+}
+
+fn btree_right(bytes: &[u8], d: &PairsData, sym: usize) -> Option<usize> {
+    let lr = bytes.get(d.btree + 3 * sym..d.btree + 3 * sym + 3)?;
+    Some(((lr[2] as usize) << 4) | (lr[1] >> 4) as usize)
+}
+
+// tablebase piece codes are 1-6 for pawn to king, plus 8 for black
+fn piece_code(piece: Piece) -> u8 {
+    (piece.piece() + 1) | if piece.color() == 0 { 8 } else { 0 }
+}
+
+// the material of one side in table name order, KQRBNP
+fn material(board: &Board, color: u8) -> String {
+    let state = board.current_state();
+    let mut name = String::new();
+    for (piece, c) in [
+        (Types::King, 'K'),
+        (Types::Queen, 'Q'),
+        (Types::Rook, 'R'),
+        (Types::Bishop, 'B'),
+        (Types::Knight, 'N'),
+        (Types::Pawn, 'P'),
+    ] {
+        for _ in 0..state.colored_piece(piece as u8, color).popcount() {
+            name.push(c);
+        }
+    }
+    name
+}
+
+fn is_capture(board: &Board, mov: Move) -> bool {
+    mov.flag() == Flag::EnPassant
+        || (mov.castling_king_to().is_none()
+            && board.current_state().piece_on_square(Square(mov.to())) != Piece::NONE)
+}
+
+fn is_zeroing(board: &Board, mov: Move) -> bool {
+    is_capture(board, mov)
+        || board
+            .current_state()
+            .piece_on_square(Square(mov.from()))
+            .piece()
+            == Types::Pawn as u8
+}
+
+// the dtz of the move that wins or loses by resetting the 50 move counter
+const fn dtz_before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        WIN => 1,
+        CURSED_WIN => 101,
+        BLESSED_LOSS => -101,
+        LOSS => -1,
+        _ => 0,
+    }
+}
+
+impl Table {
+    // name is the file name without the extension, like KRPvKR
+    fn new(name: &str, path: &str, table_type: TableType) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        let valid_side = |side: &str| {
+            side.starts_with('K')
+                && side.matches('K').count() == 1
+                && side.chars().all(|c| "KQRBNP".contains(c))
+        };
+        if !valid_side(white) || !valid_side(black) {
+            return None;
+        }
+        let piece_count = white.len() + black.len();
+        if piece_count > MAX_PIECES {
+            return None;
+        }
+
+        let count = |side: &str, c: char| side.matches(c).count();
+        let has_unique_pieces = [white, black]
+            .iter()
+            .any(|side| "QRBNP".chars().any(|c| count(side, c) == 1));
+        // the side with fewer pawns leads, it compresses better
+        let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+        let white_leads = black_pawns == 0 || (white_pawns != 0 && black_pawns >= white_pawns);
+        let pawn_count = if white_leads {
+            [white_pawns, black_pawns]
+        } else {
+            [black_pawns, white_pawns]
+        };
+
+        let mut table = Self {
+            table_type,
+            symmetric: white == black,
+            has_pawns: white_pawns + black_pawns != 0,
+            has_unique_pieces,
+            piece_count,
+            pawn_count,
+            data: None,
+        };
+        table.data = Some(table.parse(Mapping::new(path)?)?);
+        Some(table)
+    }
+
+    fn data(&self) -> Option<&TableData> {
+        self.data.as_ref()
+    }
+
+    fn sides(&self) -> usize {
+        if self.table_type == TableType::Wdl && !self.symmetric {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn files(&self) -> usize {
+        if self.has_pawns {
+            4
+        } else {
+            1
+        }
+    }
+
+    fn parse(&self, bytes: Mapping) -> Option<TableData> {
+        let magic = match self.table_type {
+            TableType::Wdl => WDL_MAGIC,
+            TableType::Dtz => DTZ_MAGIC,
+        };
+        if bytes.get(..4)? != magic {
+            return None;
+        }
+        let flags = read_u8(&bytes, 4)?;
+        if (flags & HAS_PAWNS != 0) != self.has_pawns || (flags & SPLIT != 0) == self.symmetric {
+            return None;
+        }
+
+        let sides = self.sides();
+        let files = self.files();
+        // pawns on both sides
+        let pp = self.has_pawns && self.pawn_count[1] != 0;
+        let mut pairs = vec![vec![PairsData::default(); files]; sides];
+        let mut p = 5;
+
+        for file in 0..files {
+            let order_byte = read_u8(&bytes, p)?;
+            let pawn_order_byte = if pp { read_u8(&bytes, p + 1)? } else { 0xff };
+            let order = [
+                [order_byte & 0xf, pawn_order_byte & 0xf],
+                [order_byte >> 4, pawn_order_byte >> 4],
+            ];
+            p += 1 + pp as usize;
+
+            for k in 0..self.piece_count {
+                let pieces_byte = read_u8(&bytes, p)?;
+                for (side, side_pairs) in pairs.iter_mut().enumerate() {
+                    side_pairs[file].pieces[k] = if side == 1 {
+                        pieces_byte >> 4
+                    } else {
+                        pieces_byte & 0xf
+                    };
+                }
+                p += 1;
+            }
+
+            for (side, side_pairs) in pairs.iter_mut().enumerate() {
+                self.set_groups(&mut side_pairs[file], order[side], file);
+            }
+        }
+        p += p & 1;
+
+        for file in 0..files {
+            for side_pairs in &mut pairs {
+                p = set_sizes(&mut side_pairs[file], &bytes, p)?;
+            }
+        }
+
+        let map = p;
+        if self.table_type == TableType::Dtz {
+            for d in &mut pairs[0] {
+                if d.flags & MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & WIDE != 0 {
+                    p += p & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = (p - map) / 2 + 1;
+                        p += 2 * read_u16_le(&bytes, p)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = p - map + 1;
+                        p += read_u8(&bytes, p)? as usize + 1;
+                    }
+                }
+            }
+            p += p & 1;
+        }
+
+        for file in 0..files {
+            for side_pairs in &mut pairs {
+                side_pairs[file].sparse_index = p;
+                p += side_pairs[file].sparse_index_size * 6;
+            }
+        }
+        for file in 0..files {
+            for side_pairs in &mut pairs {
+                side_pairs[file].block_length = p;
+                p += side_pairs[file].block_length_size * 2;
+            }
+        }
+        for file in 0..files {
+            for side_pairs in &mut pairs {
+                p = (p + 0x3f) & !0x3f;
+                side_pairs[file].data = p;
+                p += side_pairs[file].num_blocks * side_pairs[file].block_size;
+            }
+        }
+
+        if p > bytes.len() {
+            return None;
+        }
+        Some(TableData { bytes, pairs, map })
+    }
+
+    // splits the pieces into groups that get encoded together, and works out
+    // what each group's index gets multiplied by
+    fn set_groups(&self, d: &mut PairsData, order: [u8; 2], file: usize) {
+        let encoding = encoding();
+        let mut n = 0;
+        let mut first_len: i32 = if self.has_pawns {
+            0
+        } else if self.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        d.group_len[0] = 1;
+        for i in 1..self.piece_count {
+            first_len -= 1;
+            if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                d.group_len[n] += 1;
+            } else {
+                n += 1;
+                d.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let pp = self.has_pawns && self.pawn_count[1] != 0;
+        let mut next = if pp { 2 } else { 1 };
+        let mut free_squares = 64 - d.group_len[0] - if pp { d.group_len[1] } else { 0 };
+        let mut idx: u64 = 1;
+
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                // leading pawns or pieces
+                d.group_idx[0] = idx;
+                idx *= if self.has_pawns {
+                    encoding.lead_pawns_size[d.group_len[0]][file]
+                } else if self.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                // the other side's pawns
+                d.group_idx[1] = idx;
+                idx *= encoding.binomial[d.group_len[1]][48 - d.group_len[0]];
+            } else {
+                d.group_idx[next] = idx;
+                idx *= encoding.binomial[d.group_len[next]][free_squares];
+                free_squares -= d.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        d.group_idx[n] = idx;
+    }
+
+    // the index of the position in this table, and which pairs data it's in
+    fn encode(
+        &self,
+        data: &TableData,
+        board: &Board,
+        black_stronger: bool,
+    ) -> Result<(u64, usize, usize), DtzProbe> {
+        let encoding = encoding();
+        let state = board.current_state();
+        let mut squares = [0usize; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+
+        // tables are stored with white as the stronger side and symmetric ones only for white
+        // to move, anything else gets its colours swapped and board flipped
+        let black_to_move = board.ctm == 0;
+        let flip = (self.symmetric && black_to_move) || black_stronger;
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = usize::from(flip ^ black_to_move);
+
+        // pawn tables are split by the file of the leading pawn
+        let mut lead_pawns = Bitboard::EMPTY;
+        let mut lead_pawns_count = 0;
+        let mut file = 0;
+        if self.has_pawns {
+            let lead_color = (data.pairs[0][0].pieces[0] ^ flip_color) >> 3;
+            lead_pawns = state.colored_piece(Types::Pawn as u8, 1 - lead_color);
+            let mut pawns = lead_pawns;
+            while pawns.is_not_empty() {
+                squares[size] = pawns.pop_lsb() as usize ^ flip_squares;
+                size += 1;
+            }
+            lead_pawns_count = size;
+            let lead = (0..lead_pawns_count)
+                .rev()
+                .max_by_key(|&i| encoding.map_pawns[squares[i]])
+                .unwrap_or(0);
+            squares.swap(0, lead);
+            file = (squares[0] % 8).min(7 - squares[0] % 8);
+        }
+
+        // dtz tables only store one side to move
+        if self.table_type == TableType::Dtz {
+            let flags = data.pairs[0][file].flags;
+            if usize::from(flags & STM) != stm && (self.has_pawns || !self.symmetric) {
+                return Err(DtzProbe::ChangeStm);
+            }
+        }
+
+        let mut others = state.occupied() ^ lead_pawns;
+        while others.is_not_empty() {
+            let sq = others.pop_lsb();
+            squares[size] = sq as usize ^ flip_squares;
+            pieces[size] = piece_code(state.piece_on_square(Square(sq))) ^ flip_color;
+            size += 1;
+        }
+
+        let d = &data.pairs[stm % self.sides()][file];
+
+        // same order as the table
+        for i in lead_pawns_count..size.saturating_sub(1) {
+            for j in i + 1..size {
+                if d.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // the leading piece goes on the a-d files
+        if squares[0] % 8 > 3 {
+            for sq in squares.iter_mut().take(size) {
+                *sq ^= 7;
+            }
+        }
+
+        let mut idx;
+        if self.has_pawns {
+            idx = encoding.lead_pawn_idx[lead_pawns_count][squares[0]];
+            squares[1..lead_pawns_count].sort_by_key(|&sq| encoding.map_pawns[sq]);
+            for (i, &sq) in squares.iter().enumerate().take(lead_pawns_count).skip(1) {
+                idx += encoding.binomial[i][encoding.map_pawns[sq]];
+            }
+        } else {
+            // and below rank 5
+            if squares[0] / 8 > 3 {
+                for sq in squares.iter_mut().take(size) {
+                    *sq ^= 56;
+                }
+            }
+
+            // the first piece of the leading group that's off the a1-h8 diagonal goes below it
+            for i in 0..d.group_len[0] {
+                if off_a1h8(squares[i]) == 0 {
+                    continue;
+                }
+                if off_a1h8(squares[i]) > 0 {
+                    for sq in squares.iter_mut().take(size).skip(i) {
+                        *sq = ((*sq >> 3) | (*sq << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            idx = if self.has_unique_pieces {
+                let adjust1 = usize::from(squares[1] > squares[0]);
+                let adjust2 =
+                    usize::from(squares[2] > squares[0]) + usize::from(squares[2] > squares[1]);
+                let (rank0, rank1, rank2) = (squares[0] / 8, squares[1] / 8, squares[2] / 8);
+                if off_a1h8(squares[0]) != 0 {
+                    (encoding.map_a1d1d4[squares[0]] * 63 + (squares[1] - adjust1)) * 62
+                        + squares[2]
+                        - adjust2
+                } else if off_a1h8(squares[1]) != 0 {
+                    (6 * 63 + rank0 * 28 + encoding.map_b1h1h7[squares[1]]) * 62 + squares[2]
+                        - adjust2
+                } else if off_a1h8(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank0 * 7 * 28
+                        + (rank1 - adjust1) * 28
+                        + encoding.map_b1h1h7[squares[2]]
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank0 * 7 * 6
+                        + (rank1 - adjust1) * 6
+                        + (rank2 - adjust2)
+                }
+            } else {
+                encoding.map_kk[encoding.map_a1d1d4[squares[0]]][squares[1]]
+            } as u64;
+        }
+
+        // the rest of the groups, squares taken by earlier groups are skipped
+        idx *= d.group_idx[0];
+        let mut group_start = d.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] != 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let group_end = group_start + d.group_len[next];
+            squares[group_start..group_end].sort_unstable();
+            let mut n = 0;
+            for i in 0..d.group_len[next] {
+                let sq = squares[group_start + i];
+                let adjust = squares[..group_start]
+                    .iter()
+                    .filter(|&&other| sq > other)
+                    .count();
+                n += encoding.binomial[i + 1][sq - adjust - 8 * usize::from(remaining_pawns)];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            group_start = group_end;
+            next += 1;
+        }
+
+        Ok((idx, stm % self.sides(), file))
+    }
+
+    // what a dtz table stores is moves or plies to zeroing, maybe remapped, turn it into plies
+    fn map_score(&self, data: &TableData, file: usize, value: i32, wdl: i32) -> Option<i32> {
+        const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+        let d = &data.pairs[0][file];
+        let mut value = value as usize;
+        if d.flags & MAPPED != 0 {
+            let idx = d.map_idx[WDL_MAP[(wdl + 2) as usize]];
+            value = if d.flags & WIDE != 0 {
+                read_u16_le(&data.bytes, data.map + 2 * (idx + value))? as usize
+            } else {
+                read_u8(&data.bytes, data.map + idx + value)? as usize
+            };
+        }
+        let mut value = value as i32;
+        if (wdl == WIN && d.flags & WIN_PLIES == 0)
+            || (wdl == LOSS && d.flags & LOSS_PLIES == 0)
+            || wdl == CURSED_WIN
+            || wdl == BLESSED_LOSS
+        {
+            value *= 2;
+        }
+        Some(value + 1)
+    }
+}
+
+// fills in the sizes of a pairs data, returns where the next one starts
+fn set_sizes(d: &mut PairsData, bytes: &[u8], mut p: usize) -> Option<usize> {
+    d.flags = read_u8(bytes, p)?;
+    p += 1;
+
+    // every position has the same value, stored in place of the symbol length
+    if d.flags & SINGLE_VALUE != 0 {
+        d.min_sym_len = read_u8(bytes, p)? as usize;
+        return Some(p + 1);
+    }
+
+    let groups = d.group_len.iter().position(|&len| len == 0)?;
+    let tb_size = d.group_idx[groups];
+
+    d.block_size = 1 << read_u8(bytes, p)?;
+    d.span = 1 << read_u8(bytes, p + 1)?;
+    d.sparse_index_size = tb_size.div_ceil(d.span) as usize;
+    let padding = read_u8(bytes, p + 2)? as usize;
+    d.num_blocks = read_u32_le(bytes, p + 3)? as usize;
+    d.block_length_size = d.num_blocks + padding;
+    let max_sym_len = read_u8(bytes, p + 7)? as usize;
+    d.min_sym_len = read_u8(bytes, p + 8)? as usize;
+    p += 9;
+    if max_sym_len < d.min_sym_len || max_sym_len > 32 {
+        return None;
+    }
+    d.lowest_sym = p;
+
+    // canonical huffman, longer symbols have lower values, so base64[len] is the lowest
+    // symbol of each length padded out to 64 bits
+    let lengths = max_sym_len - d.min_sym_len + 1;
+    d.base64 = vec![0; lengths];
+    for i in (0..lengths - 1).rev() {
+        let lowest = read_u16_le(bytes, p + 2 * i)? as u64;
+        let lowest_next = read_u16_le(bytes, p + 2 * (i + 1))? as u64;
+        d.base64[i] = (d.base64[i + 1]
+            .wrapping_add(lowest)
+            .wrapping_sub(lowest_next))
+            / 2;
+    }
+    for (i, base) in d.base64.iter_mut().enumerate() {
+        *base <<= 64 - i - d.min_sym_len;
+    }
+    p += 2 * lengths;
+
+    let symbols = read_u16_le(bytes, p)? as usize;
+    p += 2;
+    d.btree = p;
+    if bytes.len() < p + 3 * symbols {
+        return None;
+    }
+
+    // recursive pairing, each symbol is a pair of smaller ones
+    d.symlen = vec![0; symbols];
+    let mut visited = vec![false; symbols];
+    for sym in 0..symbols {
+        if !visited[sym] {
+            d.symlen[sym] = set_symlen(d, bytes, sym, &mut visited)?;
+        }
+    }
+
+    Some(p + 3 * symbols + (symbols & 1))
+}
+
+fn set_symlen(d: &mut PairsData, bytes: &[u8], sym: usize, visited: &mut [bool]) -> Option<u8> {
+    visited[sym] = true;
+    let right = btree_right(bytes, d, sym)?;
+    if right == 0xfff {
+        return Some(0);
+    }
+    let left = btree_left(bytes, d, sym)?;
+    for child in [left, right] {
+        if !*visited.get(child)? {
+            d.symlen[child] = set_symlen(d, bytes, child, visited)?;
+        }
+    }
+    Some(d.symlen[left].wrapping_add(d.symlen[right]).wrapping_add(1))
+}
+
+// the value stored at idx
+fn decompress_pairs(bytes: &[u8], d: &PairsData, idx: u64) -> Option<i32> {
+    if d.flags & SINGLE_VALUE != 0 {
+        return Some(d.min_sym_len as i32);
+    }
+
+    // every span values there's a sparse index entry pointing at the value in the
+    // middle of that span, step through the blocks from there
+    let k = (idx / d.span) as usize;
+    let mut block = read_u32_le(bytes, d.sparse_index + 6 * k)? as usize;
+    let mut offset = read_u16_le(bytes, d.sparse_index + 6 * k + 4)? as i64;
+    offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+
+    let block_length = |block: usize| read_u16_le(bytes, d.block_length + 2 * block).map(i64::from);
+    while offset < 0 {
+        block = block.checked_sub(1)?;
+        offset += block_length(block)? + 1;
+    }
+    while offset > block_length(block)? {
+        offset -= block_length(block)? + 1;
+        block += 1;
+    }
+
+    // walk the huffman symbols in the block until one covers the offset
+    let mut ptr = d.data + block * d.block_size;
+    let mut buf64 = read_u64_be(bytes, ptr)?;
+    ptr += 8;
+    let mut buf64_size = 64;
+    let mut sym;
+    loop {
+        let mut len = 0;
+        while buf64 < *d.base64.get(len)? {
+            len += 1;
+        }
+        sym = ((buf64 - d.base64[len]) >> (64 - len - d.min_sym_len)) as usize;
+        sym += read_u16_le(bytes, d.lowest_sym + 2 * len)? as usize;
+
+        let sym_values = *d.symlen.get(sym)? as i64 + 1;
+        if offset < sym_values {
+            break;
+        }
+        offset -= sym_values;
+        let len = len + d.min_sym_len;
+        buf64 <<= len;
+        buf64_size -= len;
+        if buf64_size <= 32 {
+            buf64_size += 32;
+            buf64 |= (read_u32_be(bytes, ptr)? as u64) << (64 - buf64_size);
+            ptr += 4;
+        }
+    }
+
+    // then down the pairs to the single value
+    while d.symlen[sym] != 0 {
+        let left = btree_left(bytes, d, sym)?;
+        let left_values = *d.symlen.get(left)? as i64 + 1;
+        if offset < left_values {
+            sym = left;
+        } else {
+            offset -= left_values;
+            sym = btree_right(bytes, d, sym)?;
+        }
+    }
+
+    Some(btree_left(bytes, d, sym)? as i32)
+}
+
+pub struct Tablebases {
+    wdl: HashMap<String, Table>,
+    dtz: HashMap<String, Table>,
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    fn new(path: &str) -> Result<Self, String> {
+        let separator = if cfg!(windows) { ';' } else { ':' };
+        let mut tablebases = Self {
+            wdl: HashMap::new(),
+            dtz: HashMap::new(),
+            max_pieces: 0,
+        };
+        for directory in path.split(separator).filter(|dir| !dir.is_empty()) {
+            let entries = fs::read_dir(directory)
+                .map_err(|e| format!("couldn't read syzygy directory {directory}: {e}"))?;
+            for entry in entries.flatten() {
+                let file_path = entry.path();
+                let Some(name) = file_path.file_stem().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                let (tables, table_type) = match file_path.extension().and_then(|ext| ext.to_str())
+                {
+                    Some("rtbw") => (&mut tablebases.wdl, TableType::Wdl),
+                    Some("rtbz") => (&mut tablebases.dtz, TableType::Dtz),
+                    _ => continue,
+                };
+                let full_path = file_path.to_string_lossy();
+                if let Some(table) = Table::new(name, &full_path, table_type) {
+                    if table_type == TableType::Wdl {
+                        tablebases.max_pieces = tablebases.max_pieces.max(table.piece_count);
+                    }
+                    tables.entry(name.to_owned()).or_insert(table);
+                }
+            }
+        }
+        Ok(tablebases)
+    }
+
+    // the tables only know about positions without castling rights
+    fn covers(&self, board: &Board) -> bool {
+        let state = board.current_state();
+        state.castling == 0 && state.occupied().popcount() as usize <= self.max_pieces
+    }
+
+    // the table for this material, and whether black is the side the table calls white
+    fn find<'a>(tables: &'a HashMap<String, Table>, board: &Board) -> Option<(&'a Table, bool)> {
+        let white = material(board, 1);
+        let black = material(board, 0);
+        if let Some(table) = tables.get(&format!("{white}v{black}")) {
+            return Some((table, false));
+        }
+        tables
+            .get(&format!("{black}v{white}"))
+            .map(|table| (table, true))
+    }
+
+    fn probe_wdl_table(&self, board: &Board) -> Option<i32> {
+        // KvK isn't stored anywhere
+        if board.current_state().occupied().popcount() == 2 {
+            return Some(DRAW);
+        }
+        let (table, black_stronger) = Self::find(&self.wdl, board)?;
+        let data = table.data()?;
+        let (idx, side, file) = table.encode(data, board, black_stronger).ok()?;
+        Some(decompress_pairs(&data.bytes, &data.pairs[side][file], idx)? - 2)
+    }
+
+    fn probe_dtz_table(&self, board: &Board, wdl: i32) -> Option<DtzProbe> {
+        let (table, black_stronger) = Self::find(&self.dtz, board)?;
+        let data = table.data()?;
+        let (idx, side, file) = match table.encode(data, board, black_stronger) {
+            Ok(encoded) => encoded,
+            Err(change_stm) => return Some(change_stm),
+        };
+        let value = decompress_pairs(&data.bytes, &data.pairs[side][file], idx)?;
+        Some(DtzProbe::Value(table.map_score(data, file, value, wdl)?))
+    }
+
+    // tables store "don't care" values when the side to move has a winning capture, so
+    // captures (and for dtz, pawn moves) get searched and the best of them and the table wins
+    // also returns whether the best move zeroes the 50 move counter, dtz isn't stored for those
+    fn search(&self, board: &mut Board, check_zeroing: bool) -> Option<(i32, bool)> {
+        let mut moves = MoveList::new();
+        board.get_moves(&mut moves);
+        let mut move_count = 0;
+        let mut best = LOSS;
+
+        for &mov in &moves {
+            if !is_capture(board, mov)
+                && (!check_zeroing
+                    || board
+                        .current_state()
+                        .piece_on_square(Square(mov.from()))
+                        .piece()
+                        != Types::Pawn as u8)
+            {
+                continue;
+            }
+            move_count += 1;
+
+            board.make_move(mov);
+            let result = self.search(board, false);
+            board.undo_move();
+            let value = -result?.0;
+
+            if value > best {
+                best = value;
+                if value >= WIN {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        // if every move was searched the table value isn't needed (and could be wrong, tables
+        // don't know about en passant)
+        let no_more_moves = move_count != 0 && move_count == moves.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            self.probe_wdl_table(board)?
+        };
+
+        if best >= value {
+            return Some((best, best > DRAW || no_more_moves));
+        }
+        Some((value, false))
+    }
+
+    // plies to the next zeroing move, positive if the side to move wins, 0 for draws
+    fn probe_dtz(&self, board: &mut Board) -> Option<i32> {
+        let (wdl, zeroing) = self.search(board, true)?;
+        if wdl == DRAW {
+            return Some(0);
+        }
+        if zeroing {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        match self.probe_dtz_table(board, wdl)? {
+            DtzProbe::Value(dtz) => {
+                let cursed = i32::from(wdl == BLESSED_LOSS || wdl == CURSED_WIN);
+                return Some((dtz + 100 * cursed) * wdl.signum());
+            }
+            DtzProbe::ChangeStm => (),
+        }
+
+        // the table is for the other side to move, take the best dtz one ply down
+        let mut moves = MoveList::new();
+        board.get_moves(&mut moves);
+        let mut min_dtz = 0xffff;
+        for &mov in &moves {
+            let zeroing = is_zeroing(board, mov);
+            board.make_move(mov);
+            // for zeroing moves the dtz is the one from before the move
+            let dtz = if zeroing {
+                self.search(board, false)
+                    .map(|(wdl, _)| -dtz_before_zeroing(wdl))
+            } else {
+                self.probe_dtz(board).map(|dtz| -dtz)
+            };
+            let mates = dtz == Some(1) && board.in_check() && {
+                let mut replies = MoveList::new();
+                board.get_moves(&mut replies);
+                replies.is_empty()
+            };
+            board.undo_move();
+
+            let mut dtz = dtz?;
+            if mates {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == wdl.signum() {
+                min_dtz = dtz;
+            }
+        }
+
+        // no legal moves, mated
+        Some(if min_dtz == 0xffff { -1 } else { min_dtz })
+    }
+
+    // the proven result of a position in the tree, cursed wins and blessed losses count as draws
+    pub fn probe_wdl(&self, board: &mut Board) -> Option<GameResult> {
+        if !self.covers(board) || board.current_state().hm_clock != 0 {
+            return None;
+        }
+        Some(match self.search(board, false)?.0 {
+            WIN => GameResult::Win,
+            LOSS => GameResult::Loss,
+            _ => GameResult::Draw,
+        })
+    }
+
+    // the root moves that keep the best outcome with the 50 move rule in mind, winning as fast
+    // and losing as slowly as dtz allows, None if the root isn't in the tablebases
+    pub fn root_moves(&self, board: &Board) -> Option<MoveList> {
+        if !self.covers(board) {
+            return None;
+        }
+        let mut board = board.clone();
+        let hm_clock = board.current_state().hm_clock as i32;
+        let mut moves = MoveList::new();
+        board.get_moves(&mut moves);
+
+        let mut ranked = vec![];
+        for &mov in &moves {
+            board.make_move(mov);
+            let dtz = if board.current_state().hm_clock == 0 {
+                self.search(&mut board, false)
+                    .map(|(wdl, _)| dtz_before_zeroing(-wdl))
+            } else if board.is_drawn() {
+                Some(0)
+            } else {
+                // one ply further from zeroing than the position after the move
+                self.probe_dtz(&mut board).map(|dtz| -dtz - dtz.signum())
+            };
+            let mates = dtz == Some(2) && board.in_check() && {
+                let mut replies = MoveList::new();
+                board.get_moves(&mut replies);
+                replies.is_empty()
+            };
+            board.undo_move();
+
+            let dtz = if mates { 1 } else { dtz? };
+            let rank = if dtz > 0 {
+                if dtz + hm_clock <= 99 {
+                    MAX_DTZ - dtz
+                } else {
+                    MAX_DTZ / 2 - (dtz + hm_clock)
+                }
+            } else if dtz < 0 {
+                if -dtz * 2 + hm_clock < 100 {
+                    -MAX_DTZ - dtz
+                } else {
+                    -MAX_DTZ / 2 + (-dtz + hm_clock)
+                }
+            } else {
+                0
+            };
+            ranked.push((mov, rank));
+        }
+
+        let best = ranked.iter().map(|&(_, rank)| rank).max()?;
+        Some(
+            ranked
+                .into_iter()
+                .filter(|&(_, rank)| rank == best)
+                .map(|(mov, _)| mov)
+                .collect(),
+        )
+    }
+}
+
+// these need the real tables, at least everything up to 4 pieces and KRPvKR:
+// SYZYGY_PATH=<dir> cargo test --features syzygytests
+#[cfg(all(test, feature = "syzygytests"))]
+mod tests {
+    use super::*;
+
+    fn tables() -> &'static Tablebases {
+        static TABLES: OnceLock<Tablebases> = OnceLock::new();
+        TABLES.get_or_init(|| {
+            let path = std::env::var("SYZYGY_PATH").expect("SYZYGY_PATH isn't set");
+            let tables = Tablebases::new(&path).expect("couldn't read SYZYGY_PATH");
+            for name in ["KQvK", "KRvK", "KPvK", "KRPvKR"] {
+                assert!(
+                    tables.wdl.contains_key(name) && tables.dtz.contains_key(name),
+                    "{name} isn't in SYZYGY_PATH"
+                );
+            }
+            tables
+        })
+    }
+
+    fn board(fen: &str) -> Board {
+        let mut board = Board::default();
+        board.load_fen(fen).expect("test fens are valid");
+        board
+    }
+
+    fn wdl(fen: &str) -> i32 {
+        tables()
+            .search(&mut board(fen), false)
+            .expect("position is in the tables")
+            .0
+    }
+
+    fn dtz(fen: &str) -> i32 {
+        tables()
+            .probe_dtz(&mut board(fen))
+            .expect("position is in the tables")
+    }
+
+    fn root_moves(fen: &str) -> Vec<String> {
+        let mut moves: Vec<String> = tables()
+            .root_moves(&board(fen))
+            .expect("position is in the tables")
+            .iter()
+            .map(|mov| mov.to_string())
+            .collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn kqvk_wdl() {
+        assert_eq!(wdl("8/8/8/4k3/8/8/8/3QK3 w - - 0 1"), WIN);
+        assert_eq!(wdl("8/8/8/4k3/8/8/8/3QK3 b - - 0 1"), LOSS);
+        // the king takes the queen
+        assert_eq!(wdl("8/8/8/8/8/2k5/3Q4/7K b - - 0 1"), DRAW);
+    }
+
+    #[test]
+    fn krvk_wdl() {
+        assert_eq!(wdl("8/8/8/4k3/8/8/8/R3K3 w - - 0 1"), WIN);
+        assert_eq!(wdl("8/8/8/4k3/8/8/8/R3K3 b - - 0 1"), LOSS);
+        assert_eq!(wdl("8/8/8/8/8/8/1k6/R6K b - - 0 1"), DRAW);
+    }
+
+    #[test]
+    fn kpvk_wdl() {
+        // opposition decides it
+        assert_eq!(wdl("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), DRAW);
+        assert_eq!(wdl("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), LOSS);
+        // rook pawn with the king in the corner
+        assert_eq!(wdl("k7/8/K7/P7/8/8/8/8 w - - 0 1"), DRAW);
+    }
+
+    #[test]
+    fn krpvkr_wdl() {
+        // lucena
+        assert_eq!(wdl("1K1k4/1P6/8/8/8/8/r7/2R5 w - - 0 1"), WIN);
+        // philidor
+        assert_eq!(wdl("4k3/R7/7r/3KP3/8/8/8/8 b - - 0 1"), DRAW);
+    }
+
+    #[test]
+    fn mate_in_one() {
+        let fen = "k7/8/1K6/8/8/8/8/6Q1 w - - 0 1";
+        assert_eq!(dtz(fen), 1);
+        assert_eq!(root_moves(fen), ["g1g8"]);
+    }
+
+    #[test]
+    fn promotion() {
+        let fen = "8/4P3/8/8/8/8/k7/4K3 w - - 0 1";
+        assert_eq!(dtz(fen), 1);
+        // under promoting to a rook wins just as fast
+        assert_eq!(root_moves(fen), ["e7e8q", "e7e8r"]);
+    }
+
+    #[test]
+    fn losing_side_dtz() {
+        // white promotes next move, tables can round losses by a ply
+        let dtz = dtz("8/4P3/8/8/8/8/k7/4K3 b - - 0 1");
+        assert!((-3..=-2).contains(&dtz), "dtz {dtz}");
+    }
+
+    // only right after a zeroing move does the table value hold with the 50 move rule
+    #[test]
+    fn wdl_needs_fresh_clock() {
+        let mut fresh = board("8/8/8/4k3/8/8/8/3QK3 w - - 0 1");
+        assert_eq!(tables().probe_wdl(&mut fresh), Some(GameResult::Win));
+        let mut old = board("8/8/8/4k3/8/8/8/3QK3 w - - 5 20");
+        assert_eq!(tables().probe_wdl(&mut old), None);
+    }
+}
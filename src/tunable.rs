@@ -98,6 +98,16 @@ make_tunables! {
     root_pst_bonus = 4.401, 0.1, 5.0, 0.25, 0.002;
     time_divisor = 23.000, 1.0, 50.0, 2.25, 0.002;
     inc_divisor = 1.200, 1.0, 5.0, 0.225, 0.002;
+    hard_time_mult = 3.000, 1.0, 10.0, 0.25, 0.002;
+    max_time_fraction = 0.800, 0.1, 1.0, 0.05, 0.002;
+    mtg_hard_mult = 1.250, 1.0, 3.0, 0.1, 0.002;
+    tm_change_mult = 0.150, 0.0, 1.0, 0.015, 0.002;
+    tm_change_decay = 0.950, 0.5, 1.0, 0.01, 0.002;
+    tm_share_base = 1.600, 0.5, 3.0, 0.08, 0.002;
+    tm_share_mult = 1.000, 0.0, 2.0, 0.05, 0.002;
+    tm_drop_mult = 3.000, 0.0, 10.0, 0.3, 0.002;
+    tm_scale_min = 0.400, 0.1, 1.0, 0.03, 0.002;
+    tm_scale_max = 2.500, 1.0, 5.0, 0.15, 0.002;
     cpuct_visits_scale = 39.127, 1.0, 512.0, 3.2, 0.002;
 }
 
@@ -112,5 +122,15 @@ make_tunables! {
     root_pst_bonus = 2.401, 0.1, 5.0, 0.25, 0.002;
     time_divisor = 23.000, 1.0, 50.0, 2.25, 0.002;
     inc_divisor = 1.200, 1.0, 5.0, 0.225, 0.002;
+    hard_time_mult = 3.000, 1.0, 10.0, 0.25, 0.002;
+    max_time_fraction = 0.800, 0.1, 1.0, 0.05, 0.002;
+    mtg_hard_mult = 1.250, 1.0, 3.0, 0.1, 0.002;
+    tm_change_mult = 0.150, 0.0, 1.0, 0.015, 0.002;
+    tm_change_decay = 0.950, 0.5, 1.0, 0.01, 0.002;
+    tm_share_base = 1.600, 0.5, 3.0, 0.08, 0.002;
+    tm_share_mult = 1.000, 0.0, 2.0, 0.05, 0.002;
+    tm_drop_mult = 3.000, 0.0, 10.0, 0.3, 0.002;
+    tm_scale_min = 0.400, 0.1, 1.0, 0.03, 0.002;
+    tm_scale_max = 2.500, 1.0, 5.0, 0.15, 0.002;
     cpuct_visits_scale = 39.127, 1.0, 512.0, 3.2, 0.002;
 }
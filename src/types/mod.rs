@@ -23,3 +23,4 @@ pub mod square;
 use arrayvec::ArrayVec;
 use moves::Move;
 pub type MoveList = ArrayVec<Move, 218>;
+pub type PolicyList = ArrayVec<f32, 218>;
@@ -15,15 +15,22 @@
     You should have received a copy of the GNU General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
-use std::fmt;
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use crate::board::{Board, Position, SQUARE_NAMES};
+use crate::board::{Board, Position, CASTLING_DESTINATIONS, SQUARE_NAMES};
 
 use super::{
     piece::{Piece, Types},
     square::Square,
+    MoveList,
 };
 
+// UCI_Chess960, castling gets written as the king taking its own rook instead of e1g1 style
+pub static CHESS960: AtomicBool = AtomicBool::new(false);
+
 #[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
 pub struct Move(pub u16);
 
@@ -91,6 +98,17 @@ impl Move {
         self.flag() >= Flag::KnightPromo
     }
 
+    // castling moves are stored as the king taking its own rook, this is where the king actually goes
+    #[must_use]
+    pub fn castling_king_to(&self) -> Option<Square> {
+        match self.flag() {
+            flag @ (Flag::WKCastle | Flag::WQCastle | Flag::BKCastle | Flag::BQCastle) => {
+                Some(CASTLING_DESTINATIONS[flag as usize - 1].0)
+            }
+            _ => None,
+        }
+    }
+
     pub fn to_mf(&self, position: &Position) -> u16 {
         let current_flag = self.flag();
         let mut flag = match current_flag {
@@ -104,64 +122,125 @@ impl Move {
             Flag::QueenPromo => 11,
             _ => 0,
         };
+        // montyformat has the king's destination for castling
+        let to = self.castling_king_to().unwrap_or(Square(self.to()));
         // if capture add 4
-        if position.piece_on_square(Square(self.to())) != Piece(6) {
+        if self.castling_king_to().is_none() && position.piece_on_square(to) != Piece(6) {
             flag += 4;
         }
-        ((self.from() as u16) << 10) | ((self.to() as u16) << 4) | flag
+        ((self.from() as u16) << 10) | ((to.0 as u16) << 4) | flag
     }
 
-    // next: convert text format to move (look to board's ep index decoding)
-    #[must_use]
-    pub fn from_text(text: &str, board: &Board) -> Self {
+    // the move as from, to and promotion, with castling as the king moving to `to`
+    fn text(&self, to: u8) -> String {
+        let mut c: String = SQUARE_NAMES[self.from() as usize].to_owned();
+        c += SQUARE_NAMES[to as usize];
+        c += match self.flag() {
+            Flag::KnightPromo => "n",
+            Flag::BishopPromo => "b",
+            Flag::RookPromo => "r",
+            Flag::QueenPromo => "q",
+            _ => "",
+        };
+        c
+    }
+
+    // uci text, matched against the legal moves so a typo or stale move can't corrupt the board
+    pub fn from_text(text: &str, board: &Board) -> Result<Self, String> {
+        let mut moves = MoveList::new();
+        board.get_moves(&mut moves);
+        moves
+            .into_iter()
+            .find(|mov| {
+                // castling, either as the king taking its own rook or (outside of UCI_Chess960) e1g1 style
+                mov.text(mov.to()) == text
+                    || mov.castling_king_to().is_some_and(|king_to| {
+                        !CHESS960.load(Ordering::Relaxed)
+                            && mov.from().abs_diff(king_to.0) == 2
+                            && mov.text(king_to.0) == text
+                    })
+            })
+            .ok_or_else(|| format!("illegal or invalid move {text}"))
+    }
+
+    // standard algebraic notation (Nf3, exd5, e8=Q, O-O), also matched against the legal moves
+    pub fn from_san(text: &str, board: &Board) -> Result<Self, String> {
         let state = board.states.last().expect("teehee");
-        let from_text = text[..2].to_owned();
-        let to_text = text[2..4].to_owned();
-        let mut from: u8 = 0;
-        let mut to: u8 = 0;
-        let mut flag: Flag = Flag::Normal;
-        for i in 0..64 {
-            if from_text == SQUARE_NAMES[i as usize] {
-                from = i;
-                break;
-            }
-        }
-        for i in 0..64 {
-            if to_text == SQUARE_NAMES[i as usize] {
-                to = i;
-                break;
-            }
-        }
-        if text.chars().count() > 4 {
-            flag = match text.chars().last().expect("what") {
-                'n' => Flag::KnightPromo,
-                'b' => Flag::BishopPromo,
-                'r' => Flag::RookPromo,
-                'q' => Flag::QueenPromo,
-                _ => panic!("no promotion but length is over 4"),
+        let mut moves = MoveList::new();
+        board.get_moves(&mut moves);
+        let san = text.trim_end_matches(['+', '#', '!', '?']);
+
+        let candidates: MoveList = match san {
+            "O-O" | "0-0" | "O-O-O" | "0-0-0" => {
+                let king_file = if san.len() == 3 { 6 } else { 2 };
+                moves
+                    .into_iter()
+                    .filter(|mov| {
+                        mov.castling_king_to()
+                            .is_some_and(|sq| sq.file() == king_file)
+                    })
+                    .collect()
             }
-        } else {
-            let castling = state.castling;
-            if (castling & 1) != 0 && text == "e1g1" {
-                flag = Flag::WKCastle;
-            } else if (castling & 2) != 0 && text == "e1c1" {
-                flag = Flag::WQCastle;
-            } else if (castling & 4) != 0 && text == "e8g8" {
-                flag = Flag::BKCastle;
-            } else if (castling & 8) != 0 && text == "e8c8" {
-                flag = Flag::BQCastle;
-            } else if state.piece_on_square(Square(from)).piece() == Types::Pawn as u8
-                && Square(to) == state.ep_index
-            {
-                flag = Flag::EnPassant;
-            } else if state.piece_on_square(Square(from)).piece() == Types::Pawn as u8
-                && from.abs_diff(to) == 16
-            {
-                flag = Flag::DoublePush;
+            _ => {
+                let mut chars: Vec<char> = san.chars().filter(|&c| c != 'x' && c != '=').collect();
+                let piece = match chars.first() {
+                    Some('N') => Types::Knight as u8,
+                    Some('B') => Types::Bishop as u8,
+                    Some('R') => Types::Rook as u8,
+                    Some('Q') => Types::Queen as u8,
+                    Some('K') => Types::King as u8,
+                    _ => Types::Pawn as u8,
+                };
+                if piece != Types::Pawn as u8 {
+                    chars.remove(0);
+                }
+                let promotion = match chars.last() {
+                    Some('N') => Some(Flag::KnightPromo),
+                    Some('B') => Some(Flag::BishopPromo),
+                    Some('R') => Some(Flag::RookPromo),
+                    Some('Q') => Some(Flag::QueenPromo),
+                    _ => None,
+                };
+                if promotion.is_some() {
+                    chars.pop();
+                }
+                if chars.len() < 2 {
+                    return Err(format!("invalid move {text}"));
+                }
+                let to: String = chars.split_off(chars.len() - 2).into_iter().collect();
+                let to = SQUARE_NAMES[..64]
+                    .iter()
+                    .position(|&sq| sq == to)
+                    .ok_or_else(|| format!("invalid move {text}"))?;
+                // whatever is left disambiguates the from square
+                let from_file = chars.iter().find(|c| ('a'..='h').contains(c));
+                let from_rank = chars.iter().find(|c| ('1'..='8').contains(c));
+                let disambiguation =
+                    usize::from(from_file.is_some()) + usize::from(from_rank.is_some());
+                if chars.len() > disambiguation {
+                    return Err(format!("invalid move {text}"));
+                }
+                moves
+                    .into_iter()
+                    .filter(|mov| {
+                        let from = Square(mov.from());
+                        state.piece_on_square(from).piece() == piece
+                            && mov.castling_king_to().is_none()
+                            && usize::from(mov.to()) == to
+                            && (mov.is_promotion() == promotion.is_some())
+                            && promotion.as_ref().is_none_or(|flag| mov.flag() == *flag)
+                            && from_file.is_none_or(|&c| from.file() == c as u8 - b'a')
+                            && from_rank.is_none_or(|&c| from.rank() == c as u8 - b'1')
+                    })
+                    .collect()
             }
-        }
+        };
 
-        Self::new_unchecked(from, to, flag as u8)
+        match candidates.as_slice() {
+            [mov] => Ok(*mov),
+            [] => Err(format!("illegal or invalid move {text}")),
+            _ => Err(format!("ambiguous move {text}")),
+        }
     }
 
     pub fn to_other_string(&self) -> String {
@@ -171,15 +250,10 @@ impl Move {
 
 impl fmt::Display for Move {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut c: String = SQUARE_NAMES[self.from() as usize].to_owned();
-        c += SQUARE_NAMES[self.to() as usize];
-        c += match self.flag() {
-            Flag::KnightPromo => "n",
-            Flag::BishopPromo => "b",
-            Flag::RookPromo => "r",
-            Flag::QueenPromo => "q",
-            _ => "",
+        let to = match self.castling_king_to() {
+            Some(king_to) if !CHESS960.load(Ordering::Relaxed) => king_to.0,
+            _ => self.to(),
         };
-        write!(f, "{c}")
+        write!(f, "{}", self.text(to))
     }
 }
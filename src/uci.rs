@@ -16,19 +16,40 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use std::io;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use std::thread::{self, JoinHandle};
 use std::time::Instant;
 
 #[cfg(feature = "datagen")]
 use crate::datagen::MIN_KLD;
 use crate::{
     board::Board,
-    mcts::{search::Engine, time::Limiters},
+    mcts::{
+        book::load_book,
+        experience::load_experience,
+        search::{Engine, MoveSelection},
+        time::Limiters,
+    },
     movegen::lookups::BENCH_FENS,
-    nets::policy::PolicyAccumulator,
-    perft::{perft, run_perft_suite},
+    nets::{
+        policy::{load_policy_net, PolicyAccumulator},
+        value::load_value_net,
+        EMBEDDED_NET,
+    },
+    perft::{perft, run_perft_file, run_perft_suite},
+    syzygy::load_tablebases,
     tunable::Tunables,
-    types::{moves::Move, MoveList},
+    types::{
+        moves::{Move, CHESS960},
+        MoveList, PolicyList,
+    },
 };
 use crate::nets::value::ValueNetworkState;
 
@@ -37,12 +58,17 @@ const BENCH_DEPTH: u32 = 5;
 #[cfg(not(feature = "datagen"))]
 const BENCH_DEPTH: u32 = 6;
 
+const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
 pub enum CommandTypes {
     Uci,
     IsReady,
     Position,
     NewGame,
     Go,
+    Stop,
+    PonderHit,
     PrintState,
     Value,
     Perft,
@@ -59,29 +85,48 @@ pub enum CommandTypes {
     Quit,
 }
 
+#[derive(Clone, Copy)]
 pub struct UciOptions {
     pub more_info: bool,
     pub tree_size: u64,
     pub thread_count: u64,
     pub move_overhead: u128,
     pub minimal: bool,
+    pub ponder: bool,
+    pub multi_pv: usize,
+    // how many plies below the last root to look for the new one
+    pub reuse_depth: u8,
+    pub move_selection: MoveSelection,
 }
 
 impl UciOptions {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             more_info: false,
             tree_size: u64::MAX,
             thread_count: 1,
             move_overhead: 10,
             minimal: false,
+            ponder: false,
+            multi_pv: 1,
+            reuse_depth: 4,
+            move_selection: MoveSelection::Visits,
         }
     }
 }
 
+impl Default for UciOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct Manager {
     board: Board,
-    engine: Engine,
+    engine: Arc<Mutex<Engine>>,
+    abort: Arc<AtomicBool>,
+    pondering: Arc<AtomicBool>,
+    search_thread: Option<JoinHandle<()>>,
     options: UciOptions,
     limiter: Limiters,
     tunables: Tunables,
@@ -96,9 +141,18 @@ impl Default for Manager {
 impl Manager {
     #[must_use]
     pub fn new() -> Self {
+        let engine = Engine::new();
+        let abort = engine.abort_handle();
+        let pondering = engine.ponder_handle();
+        // so a go before any position command has something to search
+        let mut board = Board::default();
+        board.load_fen(STARTPOS).expect("startpos is a valid fen");
         Self {
-            board: Board::default(),
-            engine: Engine::new(),
+            board,
+            engine: Arc::new(Mutex::new(engine)),
+            abort,
+            pondering,
+            search_thread: None,
             options: UciOptions::new(),
             limiter: Limiters::new(),
             tunables: Tunables::new(),
@@ -106,14 +160,47 @@ impl Manager {
     }
     // read line from stdin and then interpret it
     pub fn get_command(&mut self) -> bool {
-        let mut buffer = String::new();
+        let mut buffer = Vec::new();
+
+        // a line that isn't utf-8 shouldn't take the engine down, it just won't parse
+        let bytes_read = match io::stdin().lock().read_until(b'\n', &mut buffer) {
+            Ok(bytes_read) => bytes_read,
+            Err(e) => {
+                println!("info string error failed to read from stdin: {e}");
+                0
+            }
+        };
+
+        // stdin closed, treat it like quit
+        if bytes_read == 0 {
+            self.stop_search();
+            return false;
+        }
+
+        self.uci_interpret_command(String::from_utf8_lossy(&buffer).trim_end())
+    }
 
-        io::stdin()
-            .read_line(&mut buffer)
-            .expect("failed to read from stdin");
+    // block until the current search (if any) has printed its bestmove
+    fn wait_for_search(&mut self) {
+        if let Some(handle) = self.search_thread.take() {
+            handle.join().expect("search thread panicked");
+        }
+    }
+
+    // the move we were pondering on got played, search normally from here on
+    pub fn ponder_hit(&mut self) {
+        self.pondering.store(false, Ordering::Relaxed);
+    }
 
-        let (command, _new_line) = buffer.split_at(buffer.len() - 1);
-        self.uci_interpret_command(command)
+    // anything that needs the engine stops the search instead of waiting on it, an infinite
+    // or ponder search would never finish and the stop after it would never get read
+    pub fn stop_search(&mut self) {
+        self.abort.store(true, Ordering::Relaxed);
+        self.wait_for_search();
+    }
+
+    fn engine(&self) -> std::sync::MutexGuard<'_, Engine> {
+        self.engine.lock().expect("engine mutex poisoned")
     }
 
     #[must_use]
@@ -129,6 +216,8 @@ impl Manager {
             "position" => CommandTypes::Position,
             "ucinewgame" => CommandTypes::NewGame,
             "go" => CommandTypes::Go,
+            "stop" => CommandTypes::Stop,
+            "ponderhit" => CommandTypes::PonderHit,
             "quit" => CommandTypes::Quit,
             "printstate" | "show" | "print" => CommandTypes::PrintState,
             "value" => CommandTypes::Value,
@@ -154,85 +243,116 @@ impl Manager {
         match command {
             CommandTypes::Uci => self.uci_uci(),
             CommandTypes::IsReady => println!("readyok"),
-            CommandTypes::Position => self.position(command_text),
-            CommandTypes::Go => self.go(command_text),
+            CommandTypes::Position => report(self.position(command_text)),
+            CommandTypes::Go => report(self.go(command_text)),
+            CommandTypes::Stop => self.stop_search(),
+            CommandTypes::PonderHit => self.ponder_hit(),
             CommandTypes::NewGame => self.new_game(),
             CommandTypes::Invalid => println!("invalid or unsupported (for now) command"),
             CommandTypes::PrintState => self.board.print_state(),
             CommandTypes::Value => println!("evaluation {}", self.board.evaluate(&mut ValueNetworkState::default())),
-            CommandTypes::Perft => self.perft(command_text),
-            CommandTypes::SplitPerft => self.split_perft(command_text),
-            CommandTypes::MakeMove => self.make_move(command_text),
-            CommandTypes::SetOption => self.set_option(command_text),
-            CommandTypes::PerftSuite => self.perft_suite(),
+            CommandTypes::Perft => report(self.perft(command_text)),
+            CommandTypes::SplitPerft => report(self.split_perft(command_text)),
+            CommandTypes::MakeMove => report(self.make_move(command_text)),
+            CommandTypes::SetOption => report(self.set_option(command_text)),
+            CommandTypes::PerftSuite => report(self.perft_suite(command_text)),
             CommandTypes::Bench => self.bench(),
             CommandTypes::GetFen => self.get_fen(),
-            CommandTypes::Policy => self.output_policy(command_text),
+            CommandTypes::Policy => report(self.output_policy(command_text)),
             CommandTypes::Tunables => self.tunables.list(),
-            CommandTypes::Quit => return false,
-            _ => panic!("invalid command type"),
+            CommandTypes::Empty => (),
+            CommandTypes::Quit => {
+                self.stop_search();
+                return false;
+            }
         }
         true
     }
 
-    pub fn set_option(&mut self, command_text: &str) {
-        let command_sections: Vec<&str> = command_text.split_ascii_whitespace().collect();
-        match command_sections[2] {
+    pub fn set_option(&mut self, command_text: &str) -> Result<(), String> {
+        self.stop_search();
+        // setoption name <name> [value <value>], where both can contain spaces
+        let mut command_split = command_text.split_ascii_whitespace().skip(1);
+        if command_split.next() != Some("name") {
+            return Err("expected setoption name <name> value <value>".to_owned());
+        }
+        let name = command_split
+            .by_ref()
+            .take_while(|&token| token != "value")
+            .collect::<Vec<_>>()
+            .join(" ");
+        let value = command_split.collect::<Vec<_>>().join(" ");
+        match name.as_str() {
             "Hash" => {
-                self.options.tree_size = command_sections[4]
-                    .parse::<u64>()
-                    .expect("not a parsable hash size");
-                self.engine.resize(self.options.tree_size as usize);
+                self.options.tree_size = parse_spin(&name, &value, 1..=1048576)?;
+                self.engine().resize(self.options.tree_size as usize);
             }
             "Threads" => {
-                self.options.thread_count = command_sections[4]
-                    .parse::<u64>()
-                    .expect("not a parsable thread count");
+                self.options.thread_count = parse_spin(&name, &value, 1..=1024)?;
             }
             "MoveOverhead" => {
-                self.options.move_overhead = command_sections[4]
-                    .parse::<u128>()
-                    .expect("not a parsable move overhead");
-                self.engine.resize(self.options.tree_size as usize);
+                self.options.move_overhead = parse_spin(&name, &value, 1..=1048576)?;
+                self.engine().resize(self.options.tree_size as usize);
+            }
+            "MoreInfo" => self.options.more_info = parse_value(&name, &value)?,
+            "Minimal" => self.options.minimal = parse_value(&name, &value)?,
+            "MultiPV" => self.options.multi_pv = parse_spin(&name, &value, 1..=218)?,
+            "TreeReuseDepth" => self.options.reuse_depth = parse_spin(&name, &value, 0..=32)?,
+            "Ponder" => self.options.ponder = parse_value(&name, &value)?,
+            "EvalFile" | "PolicyFile" => {
+                let result = if name == "EvalFile" {
+                    load_value_net(&value)
+                } else {
+                    load_policy_net(&value)
+                };
+                result?;
+                // the old tree was built with the old net
+                self.engine().new_game();
+            }
+            "MoveSelection" => {
+                self.options.move_selection = MoveSelection::parse(&value)
+                    .ok_or_else(|| format!("invalid MoveSelection '{value}'"))?;
+            }
+            "ExperienceFile" => {
+                let count = load_experience(&value)?;
+                println!("info string loaded experience for {count} positions");
+            }
+            "BookFile" => {
+                let count = load_book(&value)?;
+                println!("info string loaded {count} book entries");
             }
-            "MoreInfo" => {
-                self.options.more_info = command_sections[4]
-                    .parse::<bool>()
-                    .expect("not a parsable bool");
+            "SyzygyPath" => {
+                let count = load_tablebases(&value)?;
+                // proven results in the old tree didn't know about the tablebases
+                self.engine().new_game();
+                println!("info string found {count} tablebases");
             }
-            "Minimal" => {
-                self.options.minimal = command_sections[4]
-                    .parse::<bool>()
-                    .expect("not a parsable bool");
+            "UCI_Chess960" => {
+                let chess960 = parse_value(&name, &value)?;
+                CHESS960.store(chess960, Ordering::Relaxed);
             }
             #[cfg(feature = "tunable")]
             _ => {
-                self.tunables
-                    .set(
-                        command_sections[2],
-                        command_sections[4]
-                            .parse::<i32>()
-                            .expect("not a parsable i32"),
-                    )
-                    .expect("teehee");
+                let value = parse_value(&name, &value)?;
+                self.tunables.set(&name, value)?;
             }
             #[cfg(not(feature = "tunable"))]
-            _ => panic!("Invalid option: {}", command_sections[2]),
+            _ => return Err(format!("unknown option {name}")),
         }
+        Ok(())
     }
 
     pub fn new_game(&mut self) {
-        self.engine.new_game();
+        self.stop_search();
+        self.engine().new_game();
     }
 
-    pub fn output_policy(&mut self, command_text: &str) {
+    pub fn output_policy(&mut self, command_text: &str) -> Result<(), String> {
         let mut moves = MoveList::new();
         self.board.get_moves(&mut moves);
         let command_split: Vec<&str> = command_text.split_ascii_whitespace().collect();
         let output_count = if command_split.len() != 1 {
-            command_split[1]
-                .parse::<usize>()
-                .expect("invalid number of moves to write")
+            parse_next(&command_split, &mut 0, "number of moves to write")?
         } else {
             moves.len()
         };
@@ -240,14 +360,13 @@ impl Manager {
         // get policy values
         let mut policy_acc = PolicyAccumulator::default();
         self.board.policy_load(&mut policy_acc);
+        let mut scores = PolicyList::new();
+        self.board.get_policies(&moves, &policy_acc, &mut scores);
         let mut tuples = vec![];
         let mut policy_sum: f32 = 0.0;
-        for i in 0..moves.len() {
-            tuples.push((
-                moves[i],
-                self.board.get_policy(moves[i], &mut policy_acc).exp(),
-            ));
-            policy_sum += tuples[i].1;
+        for (&mov, score) in moves.iter().zip(scores) {
+            tuples.push((mov, score.exp()));
+            policy_sum += score.exp();
         }
         // normalize
         for tuple in tuples.iter_mut().take(moves.len()) {
@@ -259,9 +378,14 @@ impl Manager {
         for tuple in tuples.iter().take(output_count) {
             println!("{}: {}", tuple.0, tuple.1);
         }
+        Ok(())
     }
 
     pub fn bench(&mut self) {
+        self.stop_search();
+        self.abort.store(false, Ordering::Relaxed);
+        self.pondering.store(false, Ordering::Relaxed);
+        let mut engine = self.engine();
         let mut total = 0;
         let start = Instant::now();
         let mut board: Board = Board::default();
@@ -271,15 +395,16 @@ impl Manager {
         #[cfg(not(feature = "datagen"))]
         limiters.load_values(0, 0, 0, crate::uci::BENCH_DEPTH, 0);
         for string in BENCH_FENS {
-            board.load_fen(string);
-            self.engine.search(
+            board.load_fen(string).expect("bench fens are valid");
+            engine.search(
                 board.clone(),
                 limiters,
+                &[],
                 false,
                 &self.options,
                 &self.tunables,
             );
-            total += self.engine.nodes;
+            total += engine.nodes();
         }
         let duration = start.elapsed();
         println!(
@@ -289,7 +414,8 @@ impl Manager {
         );
     }
 
-    pub fn go(&mut self, command_text: &str) {
+    pub fn go(&mut self, command_text: &str) -> Result<(), String> {
+        self.stop_search();
         let command_sections: Vec<&str> = command_text.split_ascii_whitespace().collect();
         #[cfg(feature = "datagen")]
         self.limiter.load_values(0, 0, 0, 0, 0, 0.0);
@@ -305,64 +431,41 @@ impl Manager {
         let mut binc: u128 = 0;
         let mut winc: u128 = 0;
         let mut movetime: u128 = 0;
+        let mut moves_to_go = 0;
+        let mut mate = 0;
+        let mut ponder = false;
+        let mut search_moves = MoveList::new();
         while i < command_sections.len() {
             match command_sections[i] {
-                "depth" => {
-                    i += 1;
-                    if i >= command_sections.len() {
-                        panic!("missing depth");
-                    }
-
-                    depth = command_sections[i]
-                        .parse::<u32>()
-                        .expect("not a parsable depth");
-                }
-                "nodes" => {
-                    i += 1;
-                    if i >= command_sections.len() {
-                        eprintln!("Missing node count");
-                        return;
-                    }
-
-                    nodes = command_sections[i]
-                        .parse::<u128>()
-                        .expect("not a parsable node limit");
-                }
-                "wtime" | "btime" | "winc" | "binc" => {
-                    let token = command_sections[i];
-
-                    i += 1;
-                    if i >= command_sections.len() {
-                        eprintln!("Missing {token}");
-                        return;
-                    }
-
-                    let Ok(value) = command_sections[i].parse::<i128>() else {
-                        eprintln!("Invalid {} '{}'", token, command_sections[i]);
-                        return;
-                    };
-
+                "depth" => depth = parse_next(&command_sections, &mut i, "depth")?,
+                "nodes" => nodes = parse_next(&command_sections, &mut i, "node count")?,
+                token @ ("wtime" | "btime" | "winc" | "binc") => {
+                    // some guis send negative times when flagging, a clock can't go below a millisecond
+                    let value: i128 = parse_next(&command_sections, &mut i, token)?;
                     match token {
-                        "btime" => btime = value as u128,
-                        "wtime" => wtime = value as u128,
-                        "binc" => binc = value as u128,
-                        "winc" => winc = value as u128,
+                        "btime" => btime = value.max(1) as u128,
+                        "wtime" => wtime = value.max(1) as u128,
+                        "binc" => binc = value.max(0) as u128,
+                        "winc" => winc = value.max(0) as u128,
                         _ => unreachable!(),
                     }
                 }
-                "movetime" => {
-                    i += 1;
-                    if i >= command_sections.len() {
-                        eprintln!("missing movetime");
-                        return;
+                "movetime" => movetime = parse_next(&command_sections, &mut i, "movetime")?,
+                "movestogo" => moves_to_go = parse_next(&command_sections, &mut i, "movestogo")?,
+                "mate" => mate = parse_next(&command_sections, &mut i, "mate")?,
+                "infinite" => (),
+                "ponder" => ponder = true,
+                "searchmoves" => {
+                    // take moves until the next token isn't one
+                    while let Some(mov) = command_sections
+                        .get(i + 1)
+                        .and_then(|text| Move::from_text(text, &self.board).ok())
+                    {
+                        search_moves.push(mov);
+                        i += 1;
                     }
-
-                    movetime = command_sections[i]
-                        .parse::<u128>()
-                        .expect("not a parsable move time");
                 }
-                "infinite" => (),
-                _ => println!("invalid go limiter: {}", command_sections[i]),
+                token => println!("info string error invalid go limiter '{token}'"),
             }
 
             i += 1;
@@ -374,43 +477,64 @@ impl Manager {
             time = wtime;
             inc = winc;
         }
-        time -= self.options.move_overhead;
+        // keep at least a millisecond, 0 would mean no time limit at all
+        if time != 0 {
+            time = time.saturating_sub(self.options.move_overhead).max(1);
+        }
         #[cfg(feature = "datagen")]
         self.limiter.load_values(0, 0, 0, 0, 0, MIN_KLD);
         #[cfg(not(feature = "datagen"))]
         self.limiter.load_values(time, inc, nodes, depth, movetime);
-        let best_move = self.engine.search(
-            self.board.clone(),
-            self.limiter,
-            true,
-            &self.options,
-            &self.tunables,
-        );
-        println!("bestmove {best_move}");
+        self.limiter.set_moves_to_go(moves_to_go);
+        self.limiter.set_mate(mate);
+        self.abort.store(false, Ordering::Relaxed);
+        self.pondering.store(ponder, Ordering::Relaxed);
+        let engine = Arc::clone(&self.engine);
+        let board = self.board.clone();
+        let limiter = self.limiter;
+        let options = self.options;
+        let tunables = self.tunables.clone();
+        self.search_thread = Some(thread::spawn(move || {
+            let mut engine = engine.lock().expect("engine mutex poisoned");
+            let best_move = engine.search(
+                board,
+                limiter,
+                &search_moves,
+                true,
+                &options,
+                &tunables,
+            );
+            match engine.ponder_move() {
+                // no legal moves at the root
+                _ if best_move == Move::NULL_MOVE => println!("bestmove 0000"),
+                Some(ponder_move) if options.ponder => {
+                    println!("bestmove {best_move} ponder {ponder_move}")
+                }
+                _ => println!("bestmove {best_move}"),
+            }
+        }));
+        Ok(())
     }
 
-    pub fn perft(&mut self, command_text: &str) {
-        let mut command_split = command_text.split_ascii_whitespace();
-        let _first_token = command_split.next().expect("not enough tokens");
-        let second_token = command_split.next().expect("not enough tokens");
+    pub fn perft(&mut self, command_text: &str) -> Result<(), String> {
+        let command_split: Vec<&str> = command_text.split_ascii_whitespace().collect();
+        let depth = parse_next(&command_split, &mut 0, "perft depth")?;
         let start = Instant::now();
-        let nodes = perft(
-            &mut self.board,
-            second_token.parse().expect("invalid perft depth"),
-        );
+        let nodes = perft(&mut self.board, depth);
         let duration = start.elapsed();
         println!(
             "{} nodes {} nps",
             nodes,
             nodes as f64 / duration.as_secs_f64()
         );
+        Ok(())
     }
 
-    pub fn split_perft(&mut self, command_text: &str) {
-        let mut command_split = command_text.split_ascii_whitespace();
-        let _first_token = command_split.next().expect("not enough tokens");
-        let second_token = command_split.next().expect("not enough tokens");
-        let depth: u8 = second_token.parse::<u8>().expect("invalid perft depth") - 1;
+    pub fn split_perft(&mut self, command_text: &str) -> Result<(), String> {
+        let command_split: Vec<&str> = command_text.split_ascii_whitespace().collect();
+        let depth = parse_next::<u8>(&command_split, &mut 0, "perft depth")?
+            .checked_sub(1)
+            .ok_or("split perft depth must be at least 1")?;
         let mut list: MoveList = MoveList::new();
         self.board.get_moves(&mut list);
         let mut total: u64 = 0;
@@ -429,65 +553,58 @@ impl Manager {
             total,
             total as f64 / duration.as_secs_f64()
         );
+        Ok(())
     }
 
-    pub fn perft_suite(&self) {
-        run_perft_suite();
+    // perftsuite [file [max depth]], the built in suite without a file
+    pub fn perft_suite(&self, command_text: &str) -> Result<(), String> {
+        let command_split: Vec<&str> = command_text.split_ascii_whitespace().collect();
+        let Some(path) = command_split.get(1) else {
+            run_perft_suite();
+            return Ok(());
+        };
+        let max_depth = if command_split.len() > 2 {
+            parse_next(&command_split, &mut 1, "max depth")?
+        } else {
+            u8::MAX
+        };
+        run_perft_file(path, max_depth)
     }
 
-    pub fn make_move(&mut self, command_text: &str) {
-        let mut command_split = command_text.split_ascii_whitespace();
-        let _first_token = command_split.next().expect("not enough tokens");
-        let second_token = command_split.next().expect("not enough tokens");
-        self.board
-            .make_move(Move::from_text(second_token, &self.board));
+    pub fn make_move(&mut self, command_text: &str) -> Result<(), String> {
+        let move_text = command_text
+            .split_ascii_whitespace()
+            .nth(1)
+            .ok_or("missing move")?;
+        // uci text, or san since this is typed by hand
+        let mov = Move::from_text(move_text, &self.board)
+            .or_else(|_| Move::from_san(move_text, &self.board))?;
+        self.board.make_move(mov);
+        Ok(())
     }
 
-    pub fn position(&mut self, command_text: &str) {
-        let mut command_split = command_text.split_ascii_whitespace();
-        let _first_token = command_split.next().expect("not enough tokens");
-        let second_token = command_split.next().expect("not enough tokens");
-        let mut fen: String;
-        if second_token == "startpos" {
-            fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string();
-        } else if second_token == "kiwipete" {
-            fen =
-                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1".to_string();
-        } else {
-            let third_token = command_split.next().expect("not enough tokens");
-            fen = third_token.to_owned()
-                + " "
-                + command_split.next().expect("not enough tokens")
-                + " "
-                + command_split.next().expect("not enough tokens")
-                + " "
-                + command_split.next().expect("not enough tokens")
-                + " ";
-            let next_token = command_split.next();
-            match next_token {
-                Some(string) => {
-                    if string != "moves" {
-                        fen += &(string.to_owned()
-                            + " "
-                            + command_split.next().expect("not enough tokens"));
-                    }
-                }
-                None => {}
-            }
-        }
-        self.board = Board::default();
-        self.board.load_fen(&fen);
-        // if there are moves
-        match command_split.next() {
-            Some(_moves_token) => {
-                // loop through the rest of the moves
-                for move_text in command_split {
-                    let mov: Move = Move::from_text(move_text, &self.board);
-                    self.board.make_move(mov);
-                }
-            }
-            None => {}
+    pub fn position(&mut self, command_text: &str) -> Result<(), String> {
+        let mut command_split = command_text.split_ascii_whitespace().skip(1);
+        let fen = match command_split.next() {
+            Some("startpos") => STARTPOS.to_owned(),
+            Some("kiwipete") => KIWIPETE.to_owned(),
+            Some("fen") => command_split
+                .by_ref()
+                .take_while(|&token| token != "moves")
+                .collect::<Vec<_>>()
+                .join(" "),
+            Some(token) => return Err(format!("invalid position type {token}")),
+            None => return Err("missing position".to_owned()),
+        };
+        // build the new position on the side so a bad fen or move leaves the old one intact
+        let mut board = Board::default();
+        board.load_fen(&fen).map_err(|e| e.to_string())?;
+        for move_text in command_split.skip_while(|&token| token == "moves") {
+            let mov = Move::from_text(move_text, &board)?;
+            board.make_move(mov);
         }
+        self.board = board;
+        Ok(())
     }
 
     // identify itself
@@ -496,11 +613,57 @@ impl Manager {
         println!("id author Vast");
         println!("option name Minimal type check default false");
         println!("option name Hash type spin default 32 min 1 max 1048576");
-        println!("option name Threads type spin default 1 min 1 max 1");
+        println!("option name Threads type spin default 1 min 1 max 1024");
         println!("option name MoveOverhead type spin default 10 min 1 max 1048576");
         println!("option name MoreInfo type check default false");
+        println!("option name Ponder type check default false");
+        println!("option name MultiPV type spin default 1 min 1 max 218");
+        println!("option name TreeReuseDepth type spin default 4 min 0 max 32");
+        println!("option name MoveSelection type combo default Visits var Visits var LCB");
+        println!("option name EvalFile type string default {EMBEDDED_NET}");
+        println!("option name PolicyFile type string default {EMBEDDED_NET}");
+        println!("option name SyzygyPath type string default <empty>");
+        println!("option name ExperienceFile type string default <empty>");
+        println!("option name BookFile type string default <empty>");
+        println!("option name UCI_Chess960 type check default false");
         #[cfg(feature = "tunable")]
         self.tunables.print_options();
         println!("uciok");
     }
 }
+
+fn report(result: Result<(), String>) {
+    if let Err(e) = result {
+        println!("info string error {e}");
+    }
+}
+
+// parse the token after sections[*i], moving i onto it
+fn parse_next<T: FromStr>(sections: &[&str], i: &mut usize, what: &str) -> Result<T, String> {
+    *i += 1;
+    let token = sections.get(*i).ok_or_else(|| format!("missing {what}"))?;
+    token
+        .parse()
+        .map_err(|_| format!("invalid {what} '{token}'"))
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{value}' for {name}"))
+}
+
+fn parse_spin<T>(name: &str, value: &str, range: RangeInclusive<T>) -> Result<T, String>
+where
+    T: FromStr + PartialOrd + fmt::Display,
+{
+    let value = parse_value(name, value)?;
+    if !range.contains(&value) {
+        return Err(format!(
+            "{name} must be between {} and {}",
+            range.start(),
+            range.end()
+        ));
+    }
+    Ok(value)
+}
@@ -0,0 +1,54 @@
+// the sha256 build.rs checks nets with, against the published test vectors
+#[path = "../build/sha256.rs"]
+mod sha256;
+
+use sha256::{sha256, to_hex};
+
+fn hash(data: &[u8]) -> String {
+    to_hex(&sha256(data))
+}
+
+#[test]
+fn empty() {
+    assert_eq!(
+        hash(b""),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+}
+
+#[test]
+fn abc() {
+    assert_eq!(
+        hash(b"abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
+
+#[test]
+fn two_blocks() {
+    assert_eq!(
+        hash(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+}
+
+// the length still fits in the first block at 55 bytes, at 64 the padding needs a second one
+#[test]
+fn padding_edges() {
+    assert_eq!(
+        hash(&[b'a'; 55]),
+        "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318"
+    );
+    assert_eq!(
+        hash(&[b'a'; 64]),
+        "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb"
+    );
+}
+
+#[test]
+fn million_a() {
+    assert_eq!(
+        hash(&vec![b'a'; 1_000_000]),
+        "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+    );
+}